@@ -1,5 +1,64 @@
-use criterion::{Criterion, criterion_group, criterion_main};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use toychacha_rs::aead::ToyAEAD;
+use toychacha_rs::chacha::{ChaCha20, State};
+use toychacha_rs::poly;
+
+/// System allocator that counts allocations and allocated bytes.
+struct CountingAlloc;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static ALLOC_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        // SAFETY: forwarded unchanged from the caller
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: forwarded unchanged from the caller
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Returns the number of allocations and bytes allocated while running `f`.
+fn count_allocs(f: impl FnOnce()) -> (usize, usize) {
+    let (n, bytes) = (
+        ALLOCS.load(Ordering::Relaxed),
+        ALLOC_BYTES.load(Ordering::Relaxed),
+    );
+    f();
+    (
+        ALLOCS.load(Ordering::Relaxed) - n,
+        ALLOC_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+/// The previous seal: copies AAD, ciphertext and lengths into a padded
+/// `mac_data` buffer and MACs it in one call.
+fn seal_mac_data(key: [u8; 32], nonce: [u8; 12], plaintext: &mut [u8], aad: &[u8]) -> poly::Tag {
+    let otk = poly::generate_key(key, nonce);
+    ChaCha20::new(key, nonce).encrypt(plaintext, 1).unwrap();
+
+    let aad_padded = aad.len().div_ceil(16) * 16;
+    let ct_padded = plaintext.len().div_ceil(16) * 16;
+    let mut mac_data = vec![0u8; aad_padded + ct_padded + 16];
+    mac_data[..aad.len()].copy_from_slice(aad);
+    mac_data[aad_padded..aad_padded + plaintext.len()].copy_from_slice(plaintext);
+    mac_data[aad_padded + ct_padded..aad_padded + ct_padded + 8]
+        .copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    mac_data[aad_padded + ct_padded + 8..].copy_from_slice(&(plaintext.len() as u64).to_le_bytes());
+
+    poly::mac(&otk, &mac_data)
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
     let key: [u8; 32] = [
@@ -42,8 +101,53 @@ fn criterion_benchmark(c: &mut Criterion) {
     let cc = toychacha_rs::chacha::ChaCha20::new(key, nonce);
 
     c.bench_function("chacha", |b| {
-        b.iter(|| cc.encrypt(black_box(&mut plaintext), black_box(1)))
+        b.iter(|| cc.encrypt(black_box(&mut plaintext), black_box(1)).unwrap())
+    });
+
+    let state: State = State::new(&key, &nonce, 1);
+
+    let mut group = c.benchmark_group("four blocks");
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            let mut out = [0u8; 256];
+            for (i, chunk) in out.chunks_exact_mut(64).enumerate() {
+                let s: State = State::new(&key, &nonce, black_box(1 + i as u32));
+                chunk.copy_from_slice(&s.block());
+            }
+            out
+        })
     });
+    group.bench_function("portable", |b| b.iter(|| black_box(&state).wide_block()));
+    group.finish();
+
+    let aead_key = [0x42; 32];
+    let aead = ToyAEAD::new(aead_key);
+    let aad = [0x5a; 12];
+
+    let mut group = c.benchmark_group("aead seal");
+    for size in [64, 1024, 16 * 1024, 256 * 1024] {
+        let mut buf = vec![0u8; size];
+
+        let before = count_allocs(|| {
+            seal_mac_data(aead_key, nonce, &mut buf, &aad);
+        });
+        let after = count_allocs(|| {
+            aead.seal(nonce, &mut buf, &aad).unwrap();
+        });
+        println!(
+            "aead seal/{size}: mac_data copy {} allocs ({} bytes) per call, streaming {} allocs ({} bytes) per call",
+            before.0, before.1, after.0, after.1
+        );
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("mac_data copy", size), &size, |b, _| {
+            b.iter(|| seal_mac_data(aead_key, nonce, black_box(&mut buf), &aad))
+        });
+        group.bench_with_input(BenchmarkId::new("streaming", size), &size, |b, _| {
+            b.iter(|| aead.seal(nonce, black_box(&mut buf), &aad).unwrap())
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -1,4 +1,9 @@
-use crate::{chacha, poly};
+use crate::{Error, chacha, poly, wipe};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Length of the Poly1305 tag appended by the combined-mode methods.
+pub const TAG_LEN: usize = 16;
 
 pub struct ToyAEAD {
     key: [u8; 32],
@@ -9,41 +14,288 @@ impl ToyAEAD {
         Self { key }
     }
 
-    pub fn seal(&self, nonce: [u8; 12], plaintext: &mut [u8], aad: &[u8]) -> [u8; 16] {
+    pub fn new_from_slice(key: &[u8]) -> Result<Self, Error> {
+        let key = key.try_into().map_err(|_| Error::InvalidLength)?;
+        Ok(Self::new(key))
+    }
+
+    pub fn seal(
+        &self,
+        nonce: [u8; 12],
+        plaintext: &mut [u8],
+        aad: &[u8],
+    ) -> Result<[u8; 16], Error> {
+        // Counter 0 is reserved for the one-time key, so the message gets the rest
+        chacha::check_counter(plaintext.len(), 1)?;
+
         let otk = poly::generate_key(self.key, nonce);
 
         let cc = chacha::ChaCha20::new(self.key, nonce);
+        let mut mac = poly::Poly1305::new(&otk);
+        mac.update_padded(aad);
 
+        // Authenticate each chunk right after encrypting it, while it is in cache
         let ciphertext = plaintext;
-        cc.encrypt(ciphertext, 1);
+        for (i, chunk) in ciphertext.chunks_mut(CHUNK).enumerate() {
+            cc.encrypt(chunk, chunk_counter(i))?;
+            mac.update(chunk);
+        }
+
+        Ok(finish_mac(mac, aad.len(), ciphertext.len())
+            .finalize()
+            .into())
+    }
+
+    /// Decrypts `ciphertext` in place if `tag` is valid.
+    ///
+    /// Each chunk is fed to Poly1305 and then decrypted, so the tag is only
+    /// checked once the whole buffer has been decrypted. If it does not match,
+    /// the buffer is encrypted again and returned unchanged.
+    pub fn open(
+        &self,
+        nonce: [u8; 12],
+        ciphertext: &mut [u8],
+        aad: &[u8],
+        tag: [u8; 16],
+    ) -> Result<(), Error> {
+        chacha::check_counter(ciphertext.len(), 1)?;
+
+        let otk = poly::generate_key(self.key, nonce);
+
+        let cc = chacha::ChaCha20::new(self.key, nonce);
+        let mut mac = poly::Poly1305::new(&otk);
+        mac.update_padded(aad);
 
-        // Calculate padded sizes
-        let aad_padded = calc_pad_size(aad.len());
-        let ct_padded = calc_pad_size(ciphertext.len());
-        let total_size = aad_padded + ct_padded + 16; // 8 + 8 for lengths
+        // SECURITY: unverified plaintext is written into the caller's buffer
+        // while the tag is still unchecked. That is acceptable because the
+        // caller holds `&mut` to the buffer for the whole call, so nothing can
+        // observe it before the tag check, and on failure it is overwritten
+        // with the original ciphertext before returning. This saves a second
+        // pass over the data when the tag is valid.
+        for (i, chunk) in ciphertext.chunks_mut(CHUNK).enumerate() {
+            mac.update(chunk);
+            cc.encrypt(chunk, chunk_counter(i))?;
+        }
 
-        // Allocate once with zero initialization (handles padding)
-        let mut mac_data = vec![0u8; total_size];
+        if let Err(e) = finish_mac(mac, aad.len(), ciphertext.len()).verify(&tag.into()) {
+            // Encrypt again so the caller gets its ciphertext back unchanged
+            cc.encrypt(ciphertext, 1)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Seals the first `len` bytes of `buf` in place and writes the tag right
+    /// after them, returning the `ciphertext || tag` part of `buf`.
+    ///
+    /// `buf` needs [`TAG_LEN`] bytes of headroom past the plaintext.
+    pub fn seal_combined<'a>(
+        &self,
+        nonce: [u8; 12],
+        buf: &'a mut [u8],
+        len: usize,
+        aad: &[u8],
+    ) -> Result<&'a mut [u8], Error> {
+        if buf.len() < len || buf.len() - len < TAG_LEN {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let out = &mut buf[..len + TAG_LEN];
+        let (ciphertext, tag) = out.split_at_mut(len);
+        tag.copy_from_slice(&self.seal(nonce, ciphertext, aad)?);
+        Ok(out)
+    }
+
+    /// Opens `ciphertext || tag` in place and returns the plaintext part of
+    /// `buf`. On failure `buf` is left unchanged.
+    pub fn open_combined<'a>(
+        &self,
+        nonce: [u8; 12],
+        buf: &'a mut [u8],
+        aad: &[u8],
+    ) -> Result<&'a mut [u8], Error> {
+        if buf.len() < TAG_LEN {
+            return Err(Error::InvalidLength);
+        }
 
-        // Direct copy (padding areas remain zero)
-        mac_data[..aad.len()].copy_from_slice(aad);
-        mac_data[aad_padded..aad_padded + ciphertext.len()].copy_from_slice(ciphertext);
-        mac_data[aad_padded + ct_padded..aad_padded + ct_padded + 8]
-            .copy_from_slice(&(aad.len() as u64).to_le_bytes());
-        mac_data[aad_padded + ct_padded + 8..]
-            .copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+        let (ciphertext, tag) = buf.split_at_mut(buf.len() - TAG_LEN);
+        self.open(nonce, ciphertext, aad, (&*tag).try_into().unwrap())?;
+        Ok(ciphertext)
+    }
 
-        poly::mac(otk, &mac_data)
+    /// Returns `ciphertext || tag` for `plaintext` in a new buffer.
+    #[cfg(feature = "alloc")]
+    pub fn encrypt_to_vec(
+        &self,
+        nonce: [u8; 12],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::with_capacity(plaintext.len() + TAG_LEN);
+        out.extend_from_slice(plaintext);
+        out.resize(plaintext.len() + TAG_LEN, 0);
+        self.seal_combined(nonce, &mut out, plaintext.len(), aad)?;
+        Ok(out)
+    }
+
+    /// Verifies and decrypts `ciphertext || tag` into a new buffer.
+    #[cfg(feature = "alloc")]
+    pub fn decrypt_to_vec(
+        &self,
+        nonce: [u8; 12],
+        ciphertext_with_tag: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut out = ciphertext_with_tag.to_vec();
+        let len = self.open_combined(nonce, &mut out, aad)?.len();
+        out.truncate(len);
+        Ok(out)
     }
 }
 
-fn calc_pad_size(size: usize) -> usize {
-    size.div_ceil(16) * 16
+#[cfg(feature = "zeroize")]
+impl Drop for ToyAEAD {
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for ToyAEAD {}
+
+/// ChaCha20-Poly1305 key as a generic array, matching `chacha20poly1305::Key`.
+#[cfg(feature = "aead")]
+pub type Key = ::aead::Key<ToyAEAD>;
+
+/// ChaCha20-Poly1305 nonce as a generic array.
+#[cfg(feature = "aead")]
+pub type Nonce = ::aead::Nonce<ToyAEAD>;
+
+/// ChaCha20-Poly1305 tag as a generic array.
+#[cfg(feature = "aead")]
+pub type Tag = ::aead::Tag<ToyAEAD>;
+
+#[cfg(feature = "aead")]
+impl ::aead::KeySizeUser for ToyAEAD {
+    type KeySize = ::aead::consts::U32;
+}
+
+#[cfg(feature = "aead")]
+impl ::aead::KeyInit for ToyAEAD {
+    fn new(key: &::aead::Key<Self>) -> Self {
+        ToyAEAD::new((*key).into())
+    }
+}
+
+#[cfg(feature = "aead")]
+impl ::aead::AeadCore for ToyAEAD {
+    type NonceSize = ::aead::consts::U12;
+    type TagSize = ::aead::consts::U16;
+    type CiphertextOverhead = ::aead::consts::U0;
+}
+
+#[cfg(feature = "aead")]
+impl ::aead::AeadInPlace for ToyAEAD {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &::aead::Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> ::aead::Result<::aead::Tag<Self>> {
+        let tag = self.seal((*nonce).into(), buffer, associated_data)?;
+        Ok(tag.into())
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &::aead::Nonce<Self>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &::aead::Tag<Self>,
+    ) -> ::aead::Result<()> {
+        Ok(self.open((*nonce).into(), buffer, associated_data, (*tag).into())?)
+    }
+}
+
+/// ChaCha20-Poly1305 with a 24-byte nonce (draft-irtf-cfrg-xchacha Section 2).
+///
+/// Each message runs [`ToyAEAD`] under a subkey derived with
+/// [`chacha::hchacha20`] from the first 16 bytes of the nonce. The nonce is
+/// long enough to be chosen at random.
+pub struct XChaCha20Poly1305 {
+    key: [u8; 32],
+}
+
+impl XChaCha20Poly1305 {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    pub fn new_from_slice(key: &[u8]) -> Result<Self, Error> {
+        let key = key.try_into().map_err(|_| Error::InvalidLength)?;
+        Ok(Self::new(key))
+    }
+
+    pub fn seal(
+        &self,
+        nonce: [u8; 24],
+        plaintext: &mut [u8],
+        aad: &[u8],
+    ) -> Result<[u8; 16], Error> {
+        let (aead, nonce) = self.derive(nonce);
+        aead.seal(nonce, plaintext, aad)
+    }
+
+    pub fn open(
+        &self,
+        nonce: [u8; 24],
+        ciphertext: &mut [u8],
+        aad: &[u8],
+        tag: [u8; 16],
+    ) -> Result<(), Error> {
+        let (aead, nonce) = self.derive(nonce);
+        aead.open(nonce, ciphertext, aad, tag)
+    }
+
+    fn derive(&self, nonce: [u8; 24]) -> (ToyAEAD, [u8; 12]) {
+        let (mut subkey, chacha_nonce) = chacha::xchacha_subkey(&self.key, &nonce);
+        let aead = ToyAEAD::new(subkey);
+        wipe(&mut subkey);
+        (aead, chacha_nonce)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for XChaCha20Poly1305 {
+    fn drop(&mut self) {
+        wipe(&mut self.key);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for XChaCha20Poly1305 {}
+
+/// Bytes encrypted and authenticated together by `seal` and `open`.
+const CHUNK: usize = 4096;
+
+/// Block counter of the `i`th chunk; the message starts at block 1.
+fn chunk_counter(i: usize) -> u32 {
+    1u32.wrapping_add((i * (CHUNK / 64)) as u32)
+}
+
+/// Pads the ciphertext already fed to `p` and appends the lengths, completing
+/// the RFC 8439 Section 2.8 layout.
+fn finish_mac(mut p: poly::Poly1305, aad_len: usize, ciphertext_len: usize) -> poly::Poly1305 {
+    p.update_padded(&[]);
+    p.update(&(aad_len as u64).to_le_bytes());
+    p.update(&(ciphertext_len as u64).to_le_bytes());
+    p
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::vec::Vec;
 
     #[test]
     fn test_seal() {
@@ -71,7 +323,7 @@ mod tests {
         ];
 
         let aead = ToyAEAD::new(key);
-        let tag = aead.seal(nonce, &mut plaintext[..], &aad);
+        let tag = aead.seal(nonce, &mut plaintext[..], &aad).unwrap();
 
         let want = [
             0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb, 0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef,
@@ -92,4 +344,331 @@ mod tests {
         assert_eq!(plaintext, want);
         assert_eq!(tag, want_tag);
     }
+
+    #[test]
+    fn test_open() {
+        let aad = [
+            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+        ];
+        let key = [
+            0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d,
+            0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+            0x9c, 0x9d, 0x9e, 0x9f,
+        ];
+        let nonce = [
+            0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+        ];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let aead = ToyAEAD::new(key);
+
+        let mut buf = *plaintext;
+        let tag = aead.seal(nonce, &mut buf, &aad).unwrap();
+
+        {
+            let mut ciphertext = buf;
+            aead.open(nonce, &mut ciphertext, &aad, tag).unwrap();
+            assert_eq!(ciphertext, *plaintext);
+        }
+        {
+            let mut bad_tag = tag;
+            bad_tag[0] ^= 1;
+
+            let mut ciphertext = buf;
+            let result = aead.open(nonce, &mut ciphertext, &aad, bad_tag);
+            assert_eq!(result, Err(Error::TagMismatch));
+            assert_eq!(ciphertext, buf);
+        }
+        {
+            let mut ciphertext = buf;
+            ciphertext[5] ^= 1;
+            let tampered = ciphertext;
+
+            let result = aead.open(nonce, &mut ciphertext, &aad, tag);
+            assert_eq!(result, Err(Error::TagMismatch));
+            assert_eq!(ciphertext, tampered);
+        }
+        {
+            let mut ciphertext = buf;
+            let result = aead.open(nonce, &mut ciphertext, &aad[1..], tag);
+            assert_eq!(result, Err(Error::TagMismatch));
+            assert_eq!(ciphertext, buf);
+        }
+    }
+
+    #[test]
+    fn test_chunked_matches_two_pass() {
+        // Encrypt everything, then MAC everything, as seal did before chunking
+        fn two_pass(key: [u8; 32], nonce: [u8; 12], buf: &mut [u8], aad: &[u8]) -> [u8; 16] {
+            chacha::ChaCha20::new(key, nonce).encrypt(buf, 1).unwrap();
+
+            let mut p = poly::Poly1305::new(&poly::generate_key(key, nonce));
+            p.update_padded(aad);
+            p.update_padded(buf);
+            p.update(&(aad.len() as u64).to_le_bytes());
+            p.update(&(buf.len() as u64).to_le_bytes());
+            p.finalize().into()
+        }
+
+        let key = [0x42; 32];
+        let nonce = [0x24; 12];
+        let aad = [0x5a; 13];
+        let aead = ToyAEAD::new(key);
+
+        for len in [0, 1, 63, CHUNK - 1, CHUNK, CHUNK + 1, 3 * CHUNK + 17] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+
+            let mut want = plaintext.clone();
+            let want_tag = two_pass(key, nonce, &mut want, &aad);
+
+            let mut got = plaintext.clone();
+            let tag = aead.seal(nonce, &mut got, &aad).unwrap();
+            assert_eq!(got, want, "len={len}");
+            assert_eq!(tag, want_tag, "len={len}");
+
+            aead.open(nonce, &mut got, &aad, tag).unwrap();
+            assert_eq!(got, plaintext, "len={len}");
+
+            // A bad tag on a multi-chunk message still leaves the ciphertext as it was
+            let mut bad_tag = tag;
+            bad_tag[15] ^= 1;
+            let mut got = want.clone();
+            assert_eq!(
+                aead.open(nonce, &mut got, &aad, bad_tag),
+                Err(Error::TagMismatch)
+            );
+            assert_eq!(got, want, "len={len}");
+        }
+    }
+
+    #[test]
+    fn test_combined() {
+        // RFC 8439 Section 2.8.2 with the tag appended to the ciphertext
+        let aad = [
+            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+        ];
+        let key = [
+            0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d,
+            0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+            0x9c, 0x9d, 0x9e, 0x9f,
+        ];
+        let nonce = [
+            0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+        ];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let want = [
+            0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb, 0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef,
+            0x7e, 0xc2, 0xa4, 0xad, 0xed, 0x51, 0x29, 0x6e, 0x08, 0xfe, 0xa9, 0xe2, 0xb5, 0xa7,
+            0x36, 0xee, 0x62, 0xd6, 0x3d, 0xbe, 0xa4, 0x5e, 0x8c, 0xa9, 0x67, 0x12, 0x82, 0xfa,
+            0xfb, 0x69, 0xda, 0x92, 0x72, 0x8b, 0x1a, 0x71, 0xde, 0x0a, 0x9e, 0x06, 0x0b, 0x29,
+            0x05, 0xd6, 0xa5, 0xb6, 0x7e, 0xcd, 0x3b, 0x36, 0x92, 0xdd, 0xbd, 0x7f, 0x2d, 0x77,
+            0x8b, 0x8c, 0x98, 0x03, 0xae, 0xe3, 0x28, 0x09, 0x1b, 0x58, 0xfa, 0xb3, 0x24, 0xe4,
+            0xfa, 0xd6, 0x75, 0x94, 0x55, 0x85, 0x80, 0x8b, 0x48, 0x31, 0xd7, 0xbc, 0x3f, 0xf4,
+            0xde, 0xf0, 0x8e, 0x4b, 0x7a, 0x9d, 0xe5, 0x76, 0xd2, 0x65, 0x86, 0xce, 0xc6, 0x4b,
+            0x61, 0x16, 0x1a, 0xe1, 0x0b, 0x59, 0x4f, 0x09, 0xe2, 0x6a, 0x7e, 0x90, 0x2e, 0xcb,
+            0xd0, 0x60, 0x06, 0x91,
+        ];
+
+        let aead = ToyAEAD::new(key);
+
+        let mut buf = [0u8; 114 + TAG_LEN + 3];
+        buf[..114].copy_from_slice(plaintext);
+        let sealed = aead.seal_combined(nonce, &mut buf, 114, &aad).unwrap();
+        assert_eq!(sealed, want);
+
+        let mut buf = want;
+        let opened = aead.open_combined(nonce, &mut buf, &aad).unwrap();
+        assert_eq!(opened, plaintext);
+
+        // Headroom and length checks
+        let mut short = [0u8; 114 + TAG_LEN - 1];
+        assert_eq!(
+            aead.seal_combined(nonce, &mut short, 114, &aad),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(
+            aead.seal_combined(nonce, &mut short, 200, &aad),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(
+            aead.open_combined(nonce, &mut [0u8; TAG_LEN - 1], &aad),
+            Err(Error::InvalidLength)
+        );
+
+        let mut buf = want;
+        buf[129] ^= 1;
+        let tampered = buf;
+        assert_eq!(
+            aead.open_combined(nonce, &mut buf, &aad),
+            Err(Error::TagMismatch)
+        );
+        assert_eq!(buf, tampered);
+
+        #[cfg(feature = "alloc")]
+        {
+            let sealed = aead.encrypt_to_vec(nonce, plaintext, &aad).unwrap();
+            assert_eq!(sealed, want);
+            assert_eq!(
+                aead.decrypt_to_vec(nonce, &sealed, &aad).unwrap(),
+                plaintext
+            );
+            assert_eq!(
+                aead.decrypt_to_vec(nonce, &tampered, &aad),
+                Err(Error::TagMismatch)
+            );
+            assert_eq!(
+                aead.encrypt_to_vec(nonce, &[], &aad).unwrap().len(),
+                TAG_LEN
+            );
+        }
+    }
+
+    #[test]
+    fn test_new_from_slice() {
+        assert!(ToyAEAD::new_from_slice(&[0u8; 32]).is_ok());
+        assert!(matches!(
+            ToyAEAD::new_from_slice(&[0u8; 16]),
+            Err(Error::InvalidLength)
+        ));
+    }
+
+    #[test]
+    fn test_xchacha20poly1305() {
+        // draft-irtf-cfrg-xchacha-03 Appendix A.3.1
+        let aad = [
+            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+        ];
+        let key = [
+            0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d,
+            0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+            0x9c, 0x9d, 0x9e, 0x9f,
+        ];
+        let nonce = [
+            0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4c, 0x4d,
+            0x4e, 0x4f, 0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57,
+        ];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        let aead = XChaCha20Poly1305::new(key);
+
+        let mut buf = *plaintext;
+        let tag = aead.seal(nonce, &mut buf, &aad).unwrap();
+
+        let want = [
+            0xbd, 0x6d, 0x17, 0x9d, 0x3e, 0x83, 0xd4, 0x3b, 0x95, 0x76, 0x57, 0x94, 0x93, 0xc0,
+            0xe9, 0x39, 0x57, 0x2a, 0x17, 0x00, 0x25, 0x2b, 0xfa, 0xcc, 0xbe, 0xd2, 0x90, 0x2c,
+            0x21, 0x39, 0x6c, 0xbb, 0x73, 0x1c, 0x7f, 0x1b, 0x0b, 0x4a, 0xa6, 0x44, 0x0b, 0xf3,
+            0xa8, 0x2f, 0x4e, 0xda, 0x7e, 0x39, 0xae, 0x64, 0xc6, 0x70, 0x8c, 0x54, 0xc2, 0x16,
+            0xcb, 0x96, 0xb7, 0x2e, 0x12, 0x13, 0xb4, 0x52, 0x2f, 0x8c, 0x9b, 0xa4, 0x0d, 0xb5,
+            0xd9, 0x45, 0xb1, 0x1b, 0x69, 0xb9, 0x82, 0xc1, 0xbb, 0x9e, 0x3f, 0x3f, 0xac, 0x2b,
+            0xc3, 0x69, 0x48, 0x8f, 0x76, 0xb2, 0x38, 0x35, 0x65, 0xd3, 0xff, 0xf9, 0x21, 0xf9,
+            0x66, 0x4c, 0x97, 0x63, 0x7d, 0xa9, 0x76, 0x88, 0x12, 0xf6, 0x15, 0xc6, 0x8b, 0x13,
+            0xb5, 0x2e,
+        ];
+        let want_tag = [
+            0xc0, 0x87, 0x59, 0x24, 0xc1, 0xc7, 0x98, 0x79, 0x47, 0xde, 0xaf, 0xd8, 0x78, 0x0a,
+            0xcf, 0x49,
+        ];
+
+        assert_eq!(buf, want);
+        assert_eq!(tag, want_tag);
+
+        let mut ciphertext = buf;
+        aead.open(nonce, &mut ciphertext, &aad, tag).unwrap();
+        assert_eq!(ciphertext, *plaintext);
+
+        let mut ciphertext = buf;
+        ciphertext[0] ^= 0xaa;
+        let result = aead.open(nonce, &mut ciphertext, &aad, tag);
+        assert_eq!(result, Err(Error::TagMismatch));
+    }
+
+    #[cfg(feature = "aead")]
+    #[test]
+    fn test_aead_traits() {
+        use ::aead::{AeadInPlace, KeyInit};
+
+        // Generic over the traits, as a caller written for chacha20poly1305 would be
+        fn seal<A: AeadInPlace + KeyInit>(key: &[u8], nonce: &[u8], buf: &mut [u8]) -> Vec<u8> {
+            let aead = A::new_from_slice(key).unwrap();
+            let tag = aead
+                .encrypt_in_place_detached(nonce.into(), b"aad", buf)
+                .unwrap();
+            tag.to_vec()
+        }
+
+        let key = [0x42; 32];
+        let nonce = [0x24; 12];
+        let plaintext = [0xa5u8; 100];
+
+        let mut got = plaintext;
+        let tag = seal::<ToyAEAD>(&key, &nonce, &mut got);
+
+        let mut want = plaintext;
+        let want_tag = ToyAEAD::new(key).seal(nonce, &mut want, b"aad").unwrap();
+        assert_eq!(got, want);
+        assert_eq!(tag, want_tag);
+
+        let key = Key::from(key);
+        let nonce = Nonce::from(nonce);
+        let aead = <ToyAEAD as KeyInit>::new(&key);
+        let mut bad_tag = Tag::from(want_tag);
+        bad_tag[0] ^= 1;
+        assert_eq!(
+            aead.decrypt_in_place_detached(&nonce, b"aad", &mut got, &bad_tag),
+            Err(::aead::Error)
+        );
+        aead.decrypt_in_place_detached(&nonce, b"aad", &mut got, &Tag::from(want_tag))
+            .unwrap();
+        assert_eq!(got, plaintext);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize_on_drop() {
+        use core::mem::MaybeUninit;
+        use core::ptr;
+
+        let mut aead = MaybeUninit::new(ToyAEAD::new([0x42; 32]));
+        let mut xaead = MaybeUninit::new(XChaCha20Poly1305::new([0x42; 32]));
+        // SAFETY: both values are initialized and dropped exactly once here;
+        // MaybeUninit never drops them again
+        unsafe {
+            ptr::drop_in_place(aead.as_mut_ptr());
+            ptr::drop_in_place(xaead.as_mut_ptr());
+        }
+
+        let (aead, xaead) = (aead.as_ptr(), xaead.as_ptr());
+        // SAFETY: dropping leaves the memory in place, and the keys are read
+        // through raw pointers rather than references
+        unsafe {
+            assert_eq!((&raw const (*aead).key).read(), [0u8; 32]);
+            assert_eq!((&raw const (*xaead).key).read(), [0u8; 32]);
+        }
+    }
+
+    #[cfg(all(feature = "aead", feature = "alloc"))]
+    #[test]
+    fn test_aead_vec_traits() {
+        use ::aead::{Aead, KeyInit, Payload};
+
+        // The call a chacha20poly1305 user writes, resolving to the trait
+        let aead = <ToyAEAD as KeyInit>::new(&Key::from([0x42; 32]));
+        let nonce = Nonce::from([0x24; 12]);
+        let sealed = aead.encrypt(&nonce, &b"plaintext"[..]).unwrap();
+        assert_eq!(
+            sealed,
+            ToyAEAD::new([0x42; 32])
+                .encrypt_to_vec([0x24; 12], b"plaintext", b"")
+                .unwrap()
+        );
+        assert_eq!(aead.decrypt(&nonce, &sealed[..]).unwrap(), b"plaintext");
+
+        let payload = Payload {
+            msg: b"plaintext",
+            aad: b"aad",
+        };
+        let sealed = aead.encrypt(&nonce, payload).unwrap();
+        assert!(aead.decrypt(&nonce, &sealed[..]).is_err());
+    }
 }
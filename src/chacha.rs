@@ -1,9 +1,21 @@
+use crate::{Error, wipe};
+
+#[cfg(target_arch = "x86_64")]
+mod avx2;
+mod portable;
+#[cfg(target_arch = "x86_64")]
+mod sse2;
+
+/// ChaCha block function state with `ROUNDS` rounds (20 by default).
+///
+/// `State<8>` and `State<12>` give the reduced-round ChaCha8 and ChaCha12,
+/// which are faster but only suitable where a smaller security margin is fine.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct State {
+pub struct State<const ROUNDS: usize = 20> {
     x: [u32; 16],
 }
 
-impl State {
+impl<const ROUNDS: usize> State<ROUNDS> {
     pub fn new(key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> Self {
         let key_u32 = bytes_to_u32_array::<32, 8>(key);
         let nonce_u32 = bytes_to_u32_array::<12, 3>(nonce);
@@ -30,6 +42,23 @@ impl State {
         }
     }
 
+    /// Original ChaCha20 layout: a 64-bit counter in words 12-13 and a 64-bit
+    /// nonce in words 14-15, as used by libsodium's `crypto_stream_chacha20`.
+    pub fn new_legacy(key: &[u8; 32], nonce: &[u8; 8], counter: u64) -> Self {
+        let key_u32 = bytes_to_u32_array::<32, 8>(key);
+        let nonce_u32 = bytes_to_u32_array::<8, 2>(nonce);
+        State::from_u32_legacy(&key_u32, &nonce_u32, counter)
+    }
+
+    fn from_u32_legacy(key_u32: &[u32; 8], nonce_u32: &[u32; 2], counter: u64) -> Self {
+        // The high half of the counter takes the place of the first IETF nonce word
+        State::from_u32(
+            key_u32,
+            &[(counter >> 32) as u32, nonce_u32[0], nonce_u32[1]],
+            counter as u32,
+        )
+    }
+
     fn from_u32(key_u32: &[u32; 8], nonce_u32: &[u32; 3], counter: u32) -> Self {
         State {
             x: [
@@ -53,43 +82,28 @@ impl State {
         }
     }
 
-    fn quarter_round(&mut self, ai: usize, bi: usize, ci: usize, di: usize) {
-        let a = self.x[ai];
-        let b = self.x[bi];
-        let c = self.x[ci];
-        let d = self.x[di];
-
-        let (a, b, c, d) = quarter_round(a, b, c, d);
-
-        self.x[ai] = a;
-        self.x[bi] = b;
-        self.x[ci] = c;
-        self.x[di] = d;
-    }
-
     fn inner_block(&mut self) {
-        // column rounds
-        self.quarter_round(0, 4, 8, 12);
-        self.quarter_round(1, 5, 9, 13);
-        self.quarter_round(2, 6, 10, 14);
-        self.quarter_round(3, 7, 11, 15);
-
-        // diagonal rounds
-        self.quarter_round(0, 5, 10, 15);
-        self.quarter_round(1, 6, 11, 12);
-        self.quarter_round(2, 7, 8, 13);
-        self.quarter_round(3, 4, 9, 14);
+        let mut rows: [[u32; 4]; 4] = core::array::from_fn(|r| {
+            let mut row = [0u32; 4];
+            row.copy_from_slice(&self.x[4 * r..4 * (r + 1)]);
+            row
+        });
+        portable::double_round(&mut rows);
+        self.x.copy_from_slice(rows.as_flattened());
+        wipe(&mut rows);
     }
 
-    fn add(&mut self, other: &State) {
+    fn add(&mut self, other: &Self) {
         for i in 0..16 {
             self.x[i] = self.x[i].wrapping_add(other.x[i]);
         }
     }
 
     pub fn block(mut self) -> [u8; 64] {
+        const { assert!(ROUNDS.is_multiple_of(2), "ROUNDS must be even") };
+
         let state = self.clone();
-        for _ in 0..10 {
+        for _ in 0..ROUNDS / 2 {
             self.inner_block();
         }
         self.add(&state);
@@ -97,6 +111,16 @@ impl State {
         self.serialize()
     }
 
+    /// Returns the keystream of this block and the three after it, computed
+    /// together with the portable lane-array backend. Only exposed for the
+    /// benchmarks and not part of the public API.
+    #[doc(hidden)]
+    pub fn wide_block(&self) -> [u8; 64 * portable::BLOCKS] {
+        let mut out = [0u8; 64 * portable::BLOCKS];
+        portable::blocks::<ROUNDS>(&self.x, &mut out);
+        out
+    }
+
     fn serialize(&self) -> [u8; 64] {
         let mut out = [0u8; 64];
         for i in 0..16 {
@@ -107,6 +131,16 @@ impl State {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> Drop for State<ROUNDS> {
+    fn drop(&mut self) {
+        wipe(&mut self.x);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> zeroize::ZeroizeOnDrop for State<ROUNDS> {}
+
 fn bytes_to_u32_array<const N: usize, const M: usize>(bytes: &[u8; N]) -> [u32; M] {
     debug_assert_eq!(
         N,
@@ -131,57 +165,440 @@ fn rotation_n(n: u32, x: u32) -> u32 {
     x.rotate_left(n)
 }
 
-fn quarter_round(a: u32, b: u32, c: u32, d: u32) -> (u32, u32, u32, u32) {
-    let a = a.wrapping_add(b);
-    let d = rotation_n(16, d ^ a);
+/// IETF ChaCha stream cipher with `ROUNDS` rounds.
+///
+/// [`encrypt`](Self::encrypt) is stateless and starts at a given block counter.
+/// [`apply_keystream`](Self::apply_keystream) instead continues from a byte
+/// position that can be moved with [`seek`](Self::seek).
+#[derive(Clone)]
+pub struct ChaCha<const ROUNDS: usize> {
+    key_u32: [u32; 8],
+    nonce_u32: [u32; 3],
+    pos: u64,
+    // Keystream of the block containing `pos` while `pos` is mid-block
+    buffer: [u8; 64],
+}
+
+/// Number of keystream bytes addressable by the 32-bit block counter.
+pub(crate) const MAX_POS: u64 = 64 << 32;
+
+/// ChaCha20 as specified in RFC 8439.
+pub type ChaCha20 = ChaCha<20>;
+/// ChaCha reduced to 12 rounds.
+pub type ChaCha12 = ChaCha<12>;
+/// ChaCha reduced to 8 rounds.
+pub type ChaCha8 = ChaCha<8>;
+
+impl<const ROUNDS: usize> ChaCha<ROUNDS> {
+    pub fn new(key: [u8; 32], nonce: [u8; 12]) -> Self {
+        let key_u32 = bytes_to_u32_array::<32, 8>(&key);
+        let nonce_u32 = bytes_to_u32_array::<12, 3>(&nonce);
+        ChaCha {
+            key_u32,
+            nonce_u32,
+            pos: 0,
+            buffer: [0u8; 64],
+        }
+    }
+
+    pub fn new_from_slices(key: &[u8], nonce: &[u8]) -> Result<Self, Error> {
+        let key = key.try_into().map_err(|_| Error::InvalidLength)?;
+        let nonce = nonce.try_into().map_err(|_| Error::InvalidLength)?;
+        Ok(Self::new(key, nonce))
+    }
+
+    pub fn encrypt(&self, plaintext: &mut [u8], counter: u32) -> Result<(), Error> {
+        check_counter(plaintext.len(), counter)?;
+
+        let mut counter = counter;
+
+        let mut hp = plaintext;
+
+        // Whole batches of blocks go through the widest available backend
+        #[cfg(target_arch = "x86_64")]
+        let done = self.encrypt_x86(hp, counter);
+        #[cfg(not(target_arch = "x86_64"))]
+        let done = self.encrypt_portable(hp, counter);
+        counter = counter.wrapping_add((done / 64) as u32);
+        hp = &mut hp[done..];
+
+        while !hp.is_empty() {
+            let state = State::<ROUNDS>::from_u32(&self.key_u32, &self.nonce_u32, counter);
+            let mut block = state.block();
+
+            let len = core::cmp::min(hp.len(), 64);
+            for i in 0..len {
+                hp[i] ^= block[i];
+            }
+            wipe(&mut block);
+
+            counter = counter.wrapping_add(1);
+            hp = &mut hp[len..];
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts as many whole SIMD batches as fit in `buf` and returns the
+    /// number of bytes processed. The caller has already checked the counter.
+    #[cfg(target_arch = "x86_64")]
+    fn encrypt_x86(&self, buf: &mut [u8], counter: u32) -> usize {
+        let mut state = State::<ROUNDS>::from_u32(&self.key_u32, &self.nonce_u32, counter);
+        let mut done = 0;
+
+        if avx2::available() {
+            let mut ks = [0u8; 64 * avx2::BLOCKS];
+            for chunk in buf.chunks_exact_mut(ks.len()) {
+                // SAFETY: avx2::available() checked for AVX2 support
+                unsafe { avx2::blocks::<ROUNDS>(&state.x, &mut ks) };
+                xor_in_place(chunk, &ks);
+                state.x[12] = state.x[12].wrapping_add(avx2::BLOCKS as u32);
+                done += ks.len();
+            }
+            wipe(&mut ks);
+        }
+
+        let mut ks = [0u8; 64 * sse2::BLOCKS];
+        for chunk in buf[done..].chunks_exact_mut(ks.len()) {
+            // SAFETY: SSE2 is part of the x86_64 baseline
+            unsafe { sse2::blocks::<ROUNDS>(&state.x, &mut ks) };
+            xor_in_place(chunk, &ks);
+            state.x[12] = state.x[12].wrapping_add(sse2::BLOCKS as u32);
+            done += ks.len();
+        }
+        wipe(&mut ks);
+
+        done
+    }
+
+    /// Portable counterpart of `encrypt_x86` for other architectures.
+    #[cfg(not(target_arch = "x86_64"))]
+    fn encrypt_portable(&self, buf: &mut [u8], counter: u32) -> usize {
+        let mut state = State::<ROUNDS>::from_u32(&self.key_u32, &self.nonce_u32, counter);
+        let mut done = 0;
+
+        let mut ks = [0u8; 64 * portable::BLOCKS];
+        for chunk in buf.chunks_exact_mut(ks.len()) {
+            portable::blocks::<ROUNDS>(&state.x, &mut ks);
+            xor_in_place(chunk, &ks);
+            state.x[12] = state.x[12].wrapping_add(portable::BLOCKS as u32);
+            done += ks.len();
+        }
+        wipe(&mut ks);
+
+        done
+    }
+
+    /// Returns the byte offset of the next keystream byte, counted from the
+    /// start of block 0.
+    pub fn current_pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// Moves the keystream to byte offset `pos`, which need not be a multiple
+    /// of the block size.
+    pub fn seek(&mut self, pos: u64) -> Result<(), Error> {
+        if pos > MAX_POS {
+            return Err(Error::CounterExhausted);
+        }
+
+        self.pos = pos;
+        if !pos.is_multiple_of(64) {
+            self.buffer = self.keystream_block((pos / 64) as u32);
+        }
+        Ok(())
+    }
+
+    /// XORs the keystream at the current position into `buf` and advances the
+    /// position by `buf.len()`.
+    ///
+    /// Splitting a message over several calls gives the same output as one
+    /// call over the whole message.
+    pub fn apply_keystream(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if buf.len() as u64 > MAX_POS - self.pos {
+            return Err(Error::CounterExhausted);
+        }
+
+        let mut buf = buf;
+
+        // Use up the rest of a partially consumed block first
+        let offset = (self.pos % 64) as usize;
+        if offset != 0 {
+            let len = buf.len().min(64 - offset);
+            for (b, k) in buf[..len].iter_mut().zip(&self.buffer[offset..]) {
+                *b ^= k;
+            }
+            self.pos += len as u64;
+            buf = &mut buf[len..];
+        }
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        let counter = (self.pos / 64) as u32;
+        let full = buf.len() / 64 * 64;
+        let (head, tail) = buf.split_at_mut(full);
+        self.encrypt(head, counter)?;
+
+        if !tail.is_empty() {
+            self.buffer = self.keystream_block(counter.wrapping_add((full / 64) as u32));
+            for (b, k) in tail.iter_mut().zip(&self.buffer) {
+                *b ^= k;
+            }
+        }
+
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Fills `buf` with raw keystream from the current position and advances
+    /// the position by `buf.len()`.
+    pub fn write_keystream(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        buf.fill(0);
+        self.apply_keystream(buf)
+    }
+
+    /// Yields the keystream 64 bytes at a time from the current position,
+    /// ending once fewer than 64 bytes are left before the counter runs out.
+    pub fn blocks(&mut self) -> impl Iterator<Item = [u8; 64]> + '_ {
+        core::iter::from_fn(move || {
+            let mut block = [0u8; 64];
+            self.write_keystream(&mut block).ok()?;
+            Some(block)
+        })
+    }
+
+    fn keystream_block(&self, counter: u32) -> [u8; 64] {
+        State::<ROUNDS>::from_u32(&self.key_u32, &self.nonce_u32, counter).block()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> Drop for ChaCha<ROUNDS> {
+    fn drop(&mut self) {
+        wipe(&mut self.key_u32);
+        wipe(&mut self.nonce_u32);
+        wipe(&mut self.buffer);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> zeroize::ZeroizeOnDrop for ChaCha<ROUNDS> {}
+
+#[cfg(feature = "cipher")]
+impl<const ROUNDS: usize> cipher::KeySizeUser for ChaCha<ROUNDS> {
+    type KeySize = cipher::consts::U32;
+}
+
+#[cfg(feature = "cipher")]
+impl<const ROUNDS: usize> cipher::IvSizeUser for ChaCha<ROUNDS> {
+    type IvSize = cipher::consts::U12;
+}
 
-    let c = c.wrapping_add(d);
-    let b = rotation_n(12, b ^ c);
+#[cfg(feature = "cipher")]
+impl<const ROUNDS: usize> cipher::KeyIvInit for ChaCha<ROUNDS> {
+    fn new(key: &cipher::Key<Self>, iv: &cipher::Iv<Self>) -> Self {
+        ChaCha::new((*key).into(), (*iv).into())
+    }
+}
 
-    let a = a.wrapping_add(b);
-    let d = rotation_n(8, d ^ a);
+/// Fails without touching the buffer if it would run past the last block,
+/// like [`ChaCha::apply_keystream`].
+#[cfg(feature = "cipher")]
+impl<const ROUNDS: usize> cipher::StreamCipher for ChaCha<ROUNDS> {
+    fn try_apply_keystream_inout(
+        &mut self,
+        mut buf: cipher::inout::InOutBuf<'_, '_, u8>,
+    ) -> Result<(), cipher::StreamCipherError> {
+        if buf.len() as u64 > MAX_POS - self.pos {
+            return Err(cipher::StreamCipherError);
+        }
 
-    let c = c.wrapping_add(d);
-    let b = rotation_n(7, b ^ c);
+        // Input and output may be separate buffers, so go through a keystream
+        // buffer large enough for a whole SIMD batch
+        let mut ks = [0u8; 512];
+        while !buf.is_empty() {
+            let len = buf.len().min(ks.len());
+            let (mut head, tail) = buf.split_at(len);
+            self.write_keystream(&mut ks[..len])
+                .map_err(|_| cipher::StreamCipherError)?;
+            head.xor_in2out(&ks[..len]);
+            buf = tail;
+        }
+        wipe(&mut ks);
+        Ok(())
+    }
+}
 
-    (a, b, c, d)
+#[cfg(feature = "cipher")]
+impl<const ROUNDS: usize> cipher::StreamCipherSeek for ChaCha<ROUNDS> {
+    fn try_current_pos<T: cipher::SeekNum>(&self) -> Result<T, cipher::OverflowError> {
+        // SeekNum counts a partly used block as already consumed
+        let byte = (self.pos % 64) as u8;
+        let block = self.pos.div_ceil(64);
+        T::from_block_byte(block, byte, 64)
+    }
+
+    fn try_seek<T: cipher::SeekNum>(&mut self, pos: T) -> Result<(), cipher::StreamCipherError> {
+        let (block, byte): (u64, u8) = pos
+            .into_block_byte(64)
+            .map_err(|_| cipher::StreamCipherError)?;
+        let pos = block.checked_mul(64).ok_or(cipher::StreamCipherError)? + byte as u64;
+        self.seek(pos).map_err(|_| cipher::StreamCipherError)
+    }
+}
+
+fn xor_in_place(buf: &mut [u8], keystream: &[u8]) {
+    for (b, k) in buf.iter_mut().zip(keystream) {
+        *b ^= k;
+    }
 }
 
-pub struct ChaCha20 {
+/// Checks that `len` bytes of keystream fit in the blocks left from `counter`
+/// up to and including `u32::MAX` (RFC 8439 Section 2.4).
+pub(crate) fn check_counter(len: usize, counter: u32) -> Result<(), Error> {
+    let blocks = (len as u64).div_ceil(64);
+    let remaining = u32::MAX as u64 - counter as u64 + 1;
+    if blocks > remaining {
+        return Err(Error::CounterExhausted);
+    }
+    Ok(())
+}
+
+/// HChaCha20 (draft-irtf-cfrg-xchacha Section 2.2).
+///
+/// Runs the ChaCha20 rounds over the key and a 16-byte nonce and returns words
+/// 0..4 and 12..16 of the result, without the final addition, as a subkey.
+pub fn hchacha20(key: &[u8; 32], nonce: &[u8; 16]) -> [u8; 32] {
+    let mut key_u32 = bytes_to_u32_array::<32, 8>(key);
+    let nonce_u32 = bytes_to_u32_array::<16, 4>(nonce);
+
+    // The 16-byte nonce fills the counter word and the three nonce words
+    let mut state = State::<20>::from_u32(
+        &key_u32,
+        &[nonce_u32[1], nonce_u32[2], nonce_u32[3]],
+        nonce_u32[0],
+    );
+    for _ in 0..10 {
+        state.inner_block();
+    }
+
+    let mut out = [0u8; 32];
+    for (i, &w) in state.x[0..4].iter().chain(&state.x[12..16]).enumerate() {
+        out[4 * i..4 * (i + 1)].copy_from_slice(&w.to_le_bytes());
+    }
+    wipe(&mut key_u32);
+    out
+}
+
+/// Splits a 24-byte nonce into the HChaCha20 subkey and the 12-byte nonce used
+/// with it: four zero bytes followed by the last 8 bytes of the input.
+pub(crate) fn xchacha_subkey(key: &[u8; 32], nonce: &[u8; 24]) -> ([u8; 32], [u8; 12]) {
+    let (n16, n8) = nonce.split_at(16);
+    let subkey = hchacha20(key, n16.try_into().unwrap());
+
+    let mut chacha_nonce = [0u8; 12];
+    chacha_nonce[4..].copy_from_slice(n8);
+
+    (subkey, chacha_nonce)
+}
+
+/// ChaCha20 with a 24-byte nonce (draft-irtf-cfrg-xchacha Section 2.3).
+///
+/// The first 16 bytes of the nonce derive a subkey with [`hchacha20`], and the
+/// remaining 8 bytes become the nonce of an ordinary [`ChaCha20`] under it.
+/// The nonce is long enough to be chosen at random.
+pub struct XChaCha20 {
+    inner: ChaCha20,
+}
+
+impl XChaCha20 {
+    pub fn new(key: [u8; 32], nonce: [u8; 24]) -> Self {
+        let (mut subkey, chacha_nonce) = xchacha_subkey(&key, &nonce);
+        let inner = ChaCha20::new(subkey, chacha_nonce);
+        wipe(&mut subkey);
+        XChaCha20 { inner }
+    }
+
+    pub fn new_from_slices(key: &[u8], nonce: &[u8]) -> Result<Self, Error> {
+        let key = key.try_into().map_err(|_| Error::InvalidLength)?;
+        let nonce = nonce.try_into().map_err(|_| Error::InvalidLength)?;
+        Ok(Self::new(key, nonce))
+    }
+
+    pub fn encrypt(&self, plaintext: &mut [u8], counter: u32) -> Result<(), Error> {
+        self.inner.encrypt(plaintext, counter)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for XChaCha20 {}
+
+/// ChaCha20 with the original DJB layout: 64-bit counter and 8-byte nonce.
+///
+/// This is the variant implemented by libsodium's `crypto_stream_chacha20`.
+/// Its keystream differs from [`ChaCha20`] for the same key.
+pub struct ChaCha20Legacy {
     key_u32: [u32; 8],
-    nonce_u32: [u32; 3],
+    nonce_u32: [u32; 2],
 }
 
-impl ChaCha20 {
-    pub fn new(key: [u8; 32], nonce: [u8; 12]) -> Self {
+impl ChaCha20Legacy {
+    pub fn new(key: [u8; 32], nonce: [u8; 8]) -> Self {
         let key_u32 = bytes_to_u32_array::<32, 8>(&key);
-        let nonce_u32 = bytes_to_u32_array::<12, 3>(&nonce);
-        ChaCha20 { key_u32, nonce_u32 }
+        let nonce_u32 = bytes_to_u32_array::<8, 2>(&nonce);
+        ChaCha20Legacy { key_u32, nonce_u32 }
     }
 
-    pub fn encrypt(&self, plaintext: &mut [u8], counter: u32) {
+    pub fn new_from_slices(key: &[u8], nonce: &[u8]) -> Result<Self, Error> {
+        let key = key.try_into().map_err(|_| Error::InvalidLength)?;
+        let nonce = nonce.try_into().map_err(|_| Error::InvalidLength)?;
+        Ok(Self::new(key, nonce))
+    }
+
+    pub fn encrypt(&self, plaintext: &mut [u8], counter: u64) -> Result<(), Error> {
+        let blocks = (plaintext.len() as u64).div_ceil(64);
+        if blocks > 0 && counter.checked_add(blocks - 1).is_none() {
+            return Err(Error::CounterExhausted);
+        }
+
         let mut counter = counter;
 
         let mut hp = plaintext;
 
         while !hp.is_empty() {
-            let state = State::from_u32(&self.key_u32, &self.nonce_u32, counter);
-            let block = state.block();
+            let state = State::<20>::from_u32_legacy(&self.key_u32, &self.nonce_u32, counter);
+            let mut block = state.block();
 
-            let len = std::cmp::min(hp.len(), 64);
+            let len = core::cmp::min(hp.len(), 64);
             for i in 0..len {
                 hp[i] ^= block[i];
             }
+            wipe(&mut block);
 
-            counter += 1;
+            counter = counter.wrapping_add(1);
             hp = &mut hp[len..];
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for ChaCha20Legacy {
+    fn drop(&mut self) {
+        wipe(&mut self.key_u32);
+        wipe(&mut self.nonce_u32);
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for ChaCha20Legacy {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{vec, vec::Vec};
 
     #[test]
     fn test_rotation_n() {
@@ -195,39 +612,41 @@ mod tests {
         let c = 0x9b8d6f43;
         let d = 0x01234567;
 
-        let (a, b, c, d) = quarter_round(a, b, c, d);
+        let (mut a, mut b, mut c, mut d) = ([a], [b], [c], [d]);
+        portable::quarter_round(&mut a, &mut b, &mut c, &mut d);
 
-        assert_eq!(a, 0xea2a92f4);
-        assert_eq!(b, 0xcb1cf8ce);
-        assert_eq!(c, 0x4581472e);
-        assert_eq!(d, 0x5881c4bb);
+        assert_eq!(a, [0xea2a92f4]);
+        assert_eq!(b, [0xcb1cf8ce]);
+        assert_eq!(c, [0x4581472e]);
+        assert_eq!(d, [0x5881c4bb]);
     }
 
     #[test]
-    fn test_state_quarter_round() {
-        let mut s = State {
-            x: [
-                0x879531e0, 0xc5ecf37d, 0x516461b1, 0xc9a62f8a, 0x44c20ef3, 0x3390af7f, 0xd9fc690b,
-                0x2a5f714c, 0x53372767, 0xb00a5631, 0x974c541a, 0x359e9963, 0x5c971061, 0x3d631689,
-                0x2098d9d6, 0x91dbd320,
-            ],
-        };
-
-        s.quarter_round(2, 7, 8, 13);
+    fn test_double_round() {
+        // State from RFC 8439 Section 2.2.1, whose quarter round Q(2,7,8,13)
+        // is part of the diagonal half
+        let mut rows = [
+            [0x879531e0, 0xc5ecf37d, 0x516461b1, 0xc9a62f8a],
+            [0x44c20ef3, 0x3390af7f, 0xd9fc690b, 0x2a5f714c],
+            [0x53372767, 0xb00a5631, 0x974c541a, 0x359e9963],
+            [0x5c971061, 0x3d631689, 0x2098d9d6, 0x91dbd320],
+        ];
+        portable::double_round(&mut rows);
 
-        let want = State {
-            x: [
-                0x879531e0, 0xc5ecf37d, 0xbdb886dc, 0xc9a62f8a, 0x44c20ef3, 0x3390af7f, 0xd9fc690b,
-                0xcfacafd2, 0xe46bea80, 0xb00a5631, 0x974c541a, 0x359e9963, 0x5c971061, 0xccc07c79,
-                0x2098d9d6, 0x91dbd320,
-            ],
-        };
-        assert_eq!(s.x, want.x);
+        assert_eq!(
+            rows,
+            [
+                [0xca00c66d, 0x275a7912, 0x748d59dc, 0x4851de9b],
+                [0xf85cf8e2, 0x4dd5348a, 0x03da082b, 0xe1194d01],
+                [0x03d2db76, 0x2cc126c8, 0xf6ec5a4e, 0x1f8e5524],
+                [0x53d2dd35, 0xcd88cc68, 0xeb19d17f, 0x9931f9d0],
+            ]
+        );
     }
 
     #[test]
     fn test_inner_block() {
-        let mut s = State {
+        let mut s: State = State {
             x: [
                 0x61707865, 0x3320646e, 0x79622d32, 0x6b206574, 0x03020100, 0x07060504, 0x0b0a0908,
                 0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c, 0x00000001, 0x09000000,
@@ -239,7 +658,7 @@ mod tests {
             s.inner_block();
         }
 
-        let want = State {
+        let want: State = State {
             x: [
                 0x837778ab, 0xe238d763, 0xa67ae21e, 0x5950bb2f, 0xc4f2d0c7, 0xfc62bb2f, 0x8fa018fc,
                 0x3f5ec7b7, 0x335271c2, 0xf29489f3, 0xeabda8fc, 0x82e46ebd, 0xd19c12b4, 0xb04e16de,
@@ -251,7 +670,7 @@ mod tests {
 
     #[test]
     fn test_state_new() {
-        let c = State::new(
+        let c: State = State::new(
             &[
                 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
                 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
@@ -262,7 +681,7 @@ mod tests {
             ],
             1,
         );
-        let want = State {
+        let want: State = State {
             x: [
                 0x61707865, 0x3320646e, 0x79622d32, 0x6b206574, 0x03020100, 0x07060504, 0x0b0a0908,
                 0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c, 0x00000001, 0x09000000,
@@ -275,7 +694,7 @@ mod tests {
     #[test]
     fn test_state_block() {
         // Test vector from RFC 7539 Section 2.3.2
-        let c = State::new(
+        let c: State = State::new(
             &[
                 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
                 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
@@ -325,7 +744,7 @@ mod tests {
             ];
 
             let c = ChaCha20::new(key, nonce);
-            c.encrypt(&mut plaintext, 1);
+            c.encrypt(&mut plaintext, 1).unwrap();
 
             let want = [
                 0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d,
@@ -360,7 +779,7 @@ mod tests {
             ];
 
             let c = ChaCha20::new(key, nonce);
-            c.encrypt(&mut plaintext, 0);
+            c.encrypt(&mut plaintext, 0).unwrap();
 
             let want = [
                 0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
@@ -413,7 +832,7 @@ mod tests {
             ];
 
             let c = ChaCha20::new(key, nonce);
-            c.encrypt(&mut plaintext, 1);
+            c.encrypt(&mut plaintext, 1).unwrap();
 
             let want = [
                 0xa3, 0xfb, 0xf0, 0x7d, 0xf3, 0xfa, 0x2f, 0xde, 0x4f, 0x37, 0x6c, 0xa2, 0x3e, 0x82,
@@ -471,7 +890,7 @@ mod tests {
             ];
 
             let c = ChaCha20::new(key, nonce);
-            c.encrypt(&mut plaintext, 42);
+            c.encrypt(&mut plaintext, 42).unwrap();
 
             let want = [
                 0x62, 0xe6, 0x34, 0x7f, 0x95, 0xed, 0x87, 0xa4, 0x5f, 0xfa, 0xe7, 0x42, 0x6f, 0x27,
@@ -489,4 +908,692 @@ mod tests {
             assert_eq!(want, plaintext)
         }
     }
+
+    #[test]
+    fn test_chacha_encrypt_counter_exhausted() {
+        let c = ChaCha20::new([0u8; 32], [0u8; 12]);
+
+        let mut buf = [0u8; 64];
+        assert_eq!(c.encrypt(&mut buf, u32::MAX), Ok(()));
+
+        let mut buf = [0u8; 65];
+        assert_eq!(c.encrypt(&mut buf, u32::MAX), Err(Error::CounterExhausted));
+        assert_eq!(buf, [0u8; 65]);
+
+        let mut buf = [0u8; 129];
+        assert_eq!(
+            c.encrypt(&mut buf, u32::MAX - 1),
+            Err(Error::CounterExhausted)
+        );
+        assert_eq!(buf, [0u8; 129]);
+    }
+
+    #[test]
+    fn test_check_counter() {
+        assert_eq!(check_counter(0, u32::MAX), Ok(()));
+        assert_eq!(check_counter(64, u32::MAX), Ok(()));
+        assert_eq!(check_counter(65, u32::MAX), Err(Error::CounterExhausted));
+        assert_eq!(check_counter(1 << 38, 0), Ok(()));
+        assert_eq!(
+            check_counter((1 << 38) + 1, 0),
+            Err(Error::CounterExhausted)
+        );
+        assert_eq!(check_counter((1 << 38) - 64, 1), Ok(()));
+        assert_eq!(
+            check_counter((1 << 38) - 63, 1),
+            Err(Error::CounterExhausted)
+        );
+    }
+
+    #[test]
+    fn test_new_from_slices() {
+        assert!(ChaCha20::new_from_slices(&[0u8; 32], &[0u8; 12]).is_ok());
+        assert!(matches!(
+            ChaCha20::new_from_slices(&[0u8; 31], &[0u8; 12]),
+            Err(Error::InvalidLength)
+        ));
+        assert!(matches!(
+            ChaCha20::new_from_slices(&[0u8; 32], &[0u8; 8]),
+            Err(Error::InvalidLength)
+        ));
+    }
+
+    #[test]
+    fn test_hchacha20() {
+        // draft-irtf-cfrg-xchacha-03 Section 2.2.1
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
+            0x1c, 0x1d, 0x1e, 0x1f,
+        ];
+        let nonce = [
+            0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00, 0x31, 0x41,
+            0x59, 0x27,
+        ];
+
+        let subkey = hchacha20(&key, &nonce);
+
+        let want = [
+            0x82, 0x41, 0x3b, 0x42, 0x27, 0xb2, 0x7b, 0xfe, 0xd3, 0x0e, 0x42, 0x50, 0x8a, 0x87,
+            0x7d, 0x73, 0xa0, 0xf9, 0xe4, 0xd5, 0x8a, 0x74, 0xa8, 0x53, 0xc1, 0x2e, 0xc4, 0x13,
+            0x26, 0xd3, 0xec, 0xdc,
+        ];
+
+        assert_eq!(want, subkey)
+    }
+
+    #[test]
+    fn test_xchacha20_encrypt() {
+        // draft-irtf-cfrg-xchacha-03 Appendix A.3.2
+        let key = [
+            0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d,
+            0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b,
+            0x9c, 0x9d, 0x9e, 0x9f,
+        ];
+        let nonce = [
+            0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4c, 0x4d,
+            0x4e, 0x4f, 0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x58,
+        ];
+
+        let c = XChaCha20::new(key, nonce);
+
+        {
+            let mut keystream = [0u8; 304];
+            c.encrypt(&mut keystream, 1).unwrap();
+
+            let want = [
+                0x29, 0x62, 0x4b, 0x4b, 0x1b, 0x14, 0x0a, 0xce, 0x53, 0x74, 0x0e, 0x40, 0x5b, 0x21,
+                0x68, 0x54, 0x0f, 0xd7, 0xd6, 0x30, 0xc1, 0xf5, 0x36, 0xfe, 0xcd, 0x72, 0x2f, 0xc3,
+                0xcd, 0xdb, 0xa7, 0xf4, 0xcc, 0xa9, 0x8c, 0xf9, 0xe4, 0x7e, 0x5e, 0x64, 0xd1, 0x15,
+                0x45, 0x0f, 0x9b, 0x12, 0x5b, 0x54, 0x44, 0x9f, 0xf7, 0x61, 0x41, 0xca, 0x62, 0x0a,
+                0x1f, 0x9c, 0xfc, 0xab, 0x2a, 0x1a, 0x8a, 0x25, 0x5e, 0x76, 0x6a, 0x52, 0x66, 0xb8,
+                0x78, 0x84, 0x61, 0x20, 0xea, 0x64, 0xad, 0x99, 0xaa, 0x47, 0x94, 0x71, 0xe6, 0x3b,
+                0xef, 0xcb, 0xd3, 0x7c, 0xd1, 0xc2, 0x2a, 0x22, 0x1f, 0xe4, 0x62, 0x21, 0x5c, 0xf3,
+                0x2c, 0x74, 0x89, 0x5b, 0xf5, 0x05, 0x86, 0x3c, 0xcd, 0xdd, 0x48, 0xf6, 0x29, 0x16,
+                0xdc, 0x65, 0x21, 0xf1, 0xec, 0x50, 0xa5, 0xae, 0x08, 0x90, 0x3a, 0xa2, 0x59, 0xd9,
+                0xbf, 0x60, 0x7c, 0xd8, 0x02, 0x6f, 0xba, 0x54, 0x86, 0x04, 0xf1, 0xb6, 0x07, 0x2d,
+                0x91, 0xbc, 0x91, 0x24, 0x3a, 0x5b, 0x84, 0x5f, 0x7f, 0xd1, 0x71, 0xb0, 0x2e, 0xdc,
+                0x5a, 0x0a, 0x84, 0xcf, 0x28, 0xdd, 0x24, 0x11, 0x46, 0xbc, 0x37, 0x6e, 0x3f, 0x48,
+                0xdf, 0x5e, 0x7f, 0xee, 0x1d, 0x11, 0x04, 0x8c, 0x19, 0x0a, 0x3d, 0x3d, 0xeb, 0x0f,
+                0xeb, 0x64, 0xb4, 0x2d, 0x9c, 0x6f, 0xde, 0xee, 0x29, 0x0f, 0xa0, 0xe6, 0xae, 0x2c,
+                0x26, 0xc0, 0x24, 0x9e, 0xa8, 0xc1, 0x81, 0xf7, 0xe2, 0xff, 0xd1, 0x00, 0xcb, 0xe5,
+                0xfd, 0x3c, 0x4f, 0x82, 0x71, 0xd6, 0x2b, 0x15, 0x33, 0x0c, 0xb8, 0xfd, 0xcf, 0x00,
+                0xb3, 0xdf, 0x50, 0x7c, 0xa8, 0xc9, 0x24, 0xf7, 0x01, 0x7b, 0x7e, 0x71, 0x2d, 0x15,
+                0xa2, 0xeb, 0x5c, 0x50, 0x48, 0x44, 0x51, 0xe5, 0x4e, 0x1b, 0x4b, 0x99, 0x5b, 0xd8,
+                0xfd, 0xd9, 0x45, 0x97, 0xbb, 0x94, 0xd7, 0xaf, 0x0b, 0x2c, 0x04, 0xdf, 0x10, 0xba,
+                0x08, 0x90, 0x89, 0x9e, 0xd9, 0x29, 0x3a, 0x0f, 0x55, 0xb8, 0xba, 0xfa, 0x99, 0x92,
+                0x64, 0x03, 0x5f, 0x1d, 0x4f, 0xbe, 0x7f, 0xe0, 0xaa, 0xfa, 0x10, 0x9a, 0x62, 0x37,
+                0x20, 0x27, 0xe5, 0x0e, 0x10, 0xcd, 0xfe, 0xcc, 0xa1, 0x27,
+            ];
+
+            assert_eq!(want, keystream)
+        }
+        {
+            let mut plaintext = [
+                0x54, 0x68, 0x65, 0x20, 0x64, 0x68, 0x6f, 0x6c, 0x65, 0x20, 0x28, 0x70, 0x72, 0x6f,
+                0x6e, 0x6f, 0x75, 0x6e, 0x63, 0x65, 0x64, 0x20, 0x22, 0x64, 0x6f, 0x6c, 0x65, 0x22,
+                0x29, 0x20, 0x69, 0x73, 0x20, 0x61, 0x6c, 0x73, 0x6f, 0x20, 0x6b, 0x6e, 0x6f, 0x77,
+                0x6e, 0x20, 0x61, 0x73, 0x20, 0x74, 0x68, 0x65, 0x20, 0x41, 0x73, 0x69, 0x61, 0x74,
+                0x69, 0x63, 0x20, 0x77, 0x69, 0x6c, 0x64, 0x20, 0x64, 0x6f, 0x67, 0x2c, 0x20, 0x72,
+                0x65, 0x64, 0x20, 0x64, 0x6f, 0x67, 0x2c, 0x20, 0x61, 0x6e, 0x64, 0x20, 0x77, 0x68,
+                0x69, 0x73, 0x74, 0x6c, 0x69, 0x6e, 0x67, 0x20, 0x64, 0x6f, 0x67, 0x2e, 0x20, 0x49,
+                0x74, 0x20, 0x69, 0x73, 0x20, 0x61, 0x62, 0x6f, 0x75, 0x74, 0x20, 0x74, 0x68, 0x65,
+                0x20, 0x73, 0x69, 0x7a, 0x65, 0x20, 0x6f, 0x66, 0x20, 0x61, 0x20, 0x47, 0x65, 0x72,
+                0x6d, 0x61, 0x6e, 0x20, 0x73, 0x68, 0x65, 0x70, 0x68, 0x65, 0x72, 0x64, 0x20, 0x62,
+                0x75, 0x74, 0x20, 0x6c, 0x6f, 0x6f, 0x6b, 0x73, 0x20, 0x6d, 0x6f, 0x72, 0x65, 0x20,
+                0x6c, 0x69, 0x6b, 0x65, 0x20, 0x61, 0x20, 0x6c, 0x6f, 0x6e, 0x67, 0x2d, 0x6c, 0x65,
+                0x67, 0x67, 0x65, 0x64, 0x20, 0x66, 0x6f, 0x78, 0x2e, 0x20, 0x54, 0x68, 0x69, 0x73,
+                0x20, 0x68, 0x69, 0x67, 0x68, 0x6c, 0x79, 0x20, 0x65, 0x6c, 0x75, 0x73, 0x69, 0x76,
+                0x65, 0x20, 0x61, 0x6e, 0x64, 0x20, 0x73, 0x6b, 0x69, 0x6c, 0x6c, 0x65, 0x64, 0x20,
+                0x6a, 0x75, 0x6d, 0x70, 0x65, 0x72, 0x20, 0x69, 0x73, 0x20, 0x63, 0x6c, 0x61, 0x73,
+                0x73, 0x69, 0x66, 0x69, 0x65, 0x64, 0x20, 0x77, 0x69, 0x74, 0x68, 0x20, 0x77, 0x6f,
+                0x6c, 0x76, 0x65, 0x73, 0x2c, 0x20, 0x63, 0x6f, 0x79, 0x6f, 0x74, 0x65, 0x73, 0x2c,
+                0x20, 0x6a, 0x61, 0x63, 0x6b, 0x61, 0x6c, 0x73, 0x2c, 0x20, 0x61, 0x6e, 0x64, 0x20,
+                0x66, 0x6f, 0x78, 0x65, 0x73, 0x20, 0x69, 0x6e, 0x20, 0x74, 0x68, 0x65, 0x20, 0x74,
+                0x61, 0x78, 0x6f, 0x6e, 0x6f, 0x6d, 0x69, 0x63, 0x20, 0x66, 0x61, 0x6d, 0x69, 0x6c,
+                0x79, 0x20, 0x43, 0x61, 0x6e, 0x69, 0x64, 0x61, 0x65, 0x2e,
+            ];
+            c.encrypt(&mut plaintext, 1).unwrap();
+
+            let want = [
+                0x7d, 0x0a, 0x2e, 0x6b, 0x7f, 0x7c, 0x65, 0xa2, 0x36, 0x54, 0x26, 0x30, 0x29, 0x4e,
+                0x06, 0x3b, 0x7a, 0xb9, 0xb5, 0x55, 0xa5, 0xd5, 0x14, 0x9a, 0xa2, 0x1e, 0x4a, 0xe1,
+                0xe4, 0xfb, 0xce, 0x87, 0xec, 0xc8, 0xe0, 0x8a, 0x8b, 0x5e, 0x35, 0x0a, 0xbe, 0x62,
+                0x2b, 0x2f, 0xfa, 0x61, 0x7b, 0x20, 0x2c, 0xfa, 0xd7, 0x20, 0x32, 0xa3, 0x03, 0x7e,
+                0x76, 0xff, 0xdc, 0xdc, 0x43, 0x76, 0xee, 0x05, 0x3a, 0x19, 0x0d, 0x7e, 0x46, 0xca,
+                0x1d, 0xe0, 0x41, 0x44, 0x85, 0x03, 0x81, 0xb9, 0xcb, 0x29, 0xf0, 0x51, 0x91, 0x53,
+                0x86, 0xb8, 0xa7, 0x10, 0xb8, 0xac, 0x4d, 0x02, 0x7b, 0x8b, 0x05, 0x0f, 0x7c, 0xba,
+                0x58, 0x54, 0xe0, 0x28, 0xd5, 0x64, 0xe4, 0x53, 0xb8, 0xa9, 0x68, 0x82, 0x41, 0x73,
+                0xfc, 0x16, 0x48, 0x8b, 0x89, 0x70, 0xca, 0xc8, 0x28, 0xf1, 0x1a, 0xe5, 0x3c, 0xab,
+                0xd2, 0x01, 0x12, 0xf8, 0x71, 0x07, 0xdf, 0x24, 0xee, 0x61, 0x83, 0xd2, 0x27, 0x4f,
+                0xe4, 0xc8, 0xb1, 0x48, 0x55, 0x34, 0xef, 0x2c, 0x5f, 0xbc, 0x1e, 0xc2, 0x4b, 0xfc,
+                0x36, 0x63, 0xef, 0xaa, 0x08, 0xbc, 0x04, 0x7d, 0x29, 0xd2, 0x50, 0x43, 0x53, 0x2d,
+                0xb8, 0x39, 0x1a, 0x8a, 0x3d, 0x77, 0x6b, 0xf4, 0x37, 0x2a, 0x69, 0x55, 0x82, 0x7c,
+                0xcb, 0x0c, 0xdd, 0x4a, 0xf4, 0x03, 0xa7, 0xce, 0x4c, 0x63, 0xd5, 0x95, 0xc7, 0x5a,
+                0x43, 0xe0, 0x45, 0xf0, 0xcc, 0xe1, 0xf2, 0x9c, 0x8b, 0x93, 0xbd, 0x65, 0xaf, 0xc5,
+                0x97, 0x49, 0x22, 0xf2, 0x14, 0xa4, 0x0b, 0x7c, 0x40, 0x2c, 0xdb, 0x91, 0xae, 0x73,
+                0xc0, 0xb6, 0x36, 0x15, 0xcd, 0xad, 0x04, 0x80, 0x68, 0x0f, 0x16, 0x51, 0x5a, 0x7a,
+                0xce, 0x9d, 0x39, 0x23, 0x64, 0x64, 0x32, 0x8a, 0x37, 0x74, 0x3f, 0xfc, 0x28, 0xf4,
+                0xdd, 0xb3, 0x24, 0xf4, 0xd0, 0xf5, 0xbb, 0xdc, 0x27, 0x0c, 0x65, 0xb1, 0x74, 0x9a,
+                0x6e, 0xff, 0xf1, 0xfb, 0xaa, 0x09, 0x53, 0x61, 0x75, 0xcc, 0xd2, 0x9f, 0xb9, 0xe6,
+                0x05, 0x7b, 0x30, 0x73, 0x20, 0xd3, 0x16, 0x83, 0x8a, 0x9c, 0x71, 0xf7, 0x0b, 0x5b,
+                0x59, 0x07, 0xa6, 0x6f, 0x7e, 0xa4, 0x9a, 0xad, 0xc4, 0x09,
+            ];
+
+            assert_eq!(want, plaintext)
+        }
+    }
+
+    #[test]
+    fn test_chacha_legacy_encrypt() {
+        // Keystream test vectors from draft-agl-tls-chacha20poly1305-04 Section 7
+        {
+            let key: [u8; 32] = [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+            ];
+            let nonce: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+            let mut plaintext = [0u8; 64];
+
+            let c = ChaCha20Legacy::new(key, nonce);
+            c.encrypt(&mut plaintext, 0).unwrap();
+
+            let want = [
+                0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+                0xbd, 0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc,
+                0x8b, 0x77, 0x0d, 0xc7, 0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d, 0x77, 0x24,
+                0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37, 0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
+                0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86,
+            ];
+
+            assert_eq!(want, plaintext)
+        }
+        {
+            let key: [u8; 32] = [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+            ];
+            let nonce: [u8; 8] = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+            let mut plaintext = [0u8; 64];
+
+            let c = ChaCha20Legacy::new(key, nonce);
+            c.encrypt(&mut plaintext, 0).unwrap();
+
+            let want = [
+                0xef, 0x3f, 0xdf, 0xd6, 0xc6, 0x15, 0x78, 0xfb, 0xf5, 0xcf, 0x35, 0xbd, 0x3d, 0xd3,
+                0x3b, 0x80, 0x09, 0x63, 0x16, 0x34, 0xd2, 0x1e, 0x42, 0xac, 0x33, 0x96, 0x0b, 0xd1,
+                0x38, 0xe5, 0x0d, 0x32, 0x11, 0x1e, 0x4c, 0xaf, 0x23, 0x7e, 0xe5, 0x3c, 0xa8, 0xad,
+                0x64, 0x26, 0x19, 0x4a, 0x88, 0x54, 0x5d, 0xdc, 0x49, 0x7a, 0x0b, 0x46, 0x6e, 0x7d,
+                0x6b, 0xbd, 0xb0, 0x04, 0x1b, 0x2f, 0x58, 0x6b,
+            ];
+
+            assert_eq!(want, plaintext)
+        }
+        {
+            let key: [u8; 32] = [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+            ];
+            let nonce: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+            let mut plaintext = [0u8; 60];
+
+            let c = ChaCha20Legacy::new(key, nonce);
+            c.encrypt(&mut plaintext, 0).unwrap();
+
+            let want = [
+                0xde, 0x9c, 0xba, 0x7b, 0xf3, 0xd6, 0x9e, 0xf5, 0xe7, 0x86, 0xdc, 0x63, 0x97, 0x3f,
+                0x65, 0x3a, 0x0b, 0x49, 0xe0, 0x15, 0xad, 0xbf, 0xf7, 0x13, 0x4f, 0xcb, 0x7d, 0xf1,
+                0x37, 0x82, 0x10, 0x31, 0xe8, 0x5a, 0x05, 0x02, 0x78, 0xa7, 0x08, 0x45, 0x27, 0x21,
+                0x4f, 0x73, 0xef, 0xc7, 0xfa, 0x5b, 0x52, 0x77, 0x06, 0x2e, 0xb7, 0xa0, 0x43, 0x3e,
+                0x44, 0x5f, 0x41, 0xe3,
+            ];
+
+            assert_eq!(want, plaintext)
+        }
+        {
+            let key: [u8; 32] = [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x01,
+            ];
+            let nonce: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+            let mut plaintext = [0u8; 64];
+
+            let c = ChaCha20Legacy::new(key, nonce);
+            c.encrypt(&mut plaintext, 0).unwrap();
+
+            let want = [
+                0x45, 0x40, 0xf0, 0x5a, 0x9f, 0x1f, 0xb2, 0x96, 0xd7, 0x73, 0x6e, 0x7b, 0x20, 0x8e,
+                0x3c, 0x96, 0xeb, 0x4f, 0xe1, 0x83, 0x46, 0x88, 0xd2, 0x60, 0x4f, 0x45, 0x09, 0x52,
+                0xed, 0x43, 0x2d, 0x41, 0xbb, 0xe2, 0xa0, 0xb6, 0xea, 0x75, 0x66, 0xd2, 0xa5, 0xd1,
+                0xe7, 0xe2, 0x0d, 0x42, 0xaf, 0x2c, 0x53, 0xd7, 0x92, 0xb1, 0xc4, 0x3f, 0xea, 0x81,
+                0x7e, 0x9a, 0xd2, 0x75, 0xae, 0x54, 0x69, 0x63,
+            ];
+
+            assert_eq!(want, plaintext)
+        }
+    }
+
+    #[test]
+    fn test_chacha_legacy_counter() {
+        let c = ChaCha20Legacy::new([0x11; 32], [0x22; 8]);
+
+        // The counter carries from word 12 into word 13
+        let mut buf = [0u8; 128];
+        c.encrypt(&mut buf, u32::MAX as u64).unwrap();
+
+        let mut second = [0u8; 64];
+        c.encrypt(&mut second, 1 << 32).unwrap();
+        assert_eq!(buf[64..], second);
+
+        let want = State::<20>::new_legacy(&[0x11; 32], &[0x22; 8], 1 << 32).block();
+        assert_eq!(second, want);
+
+        let mut buf = [0u8; 65];
+        assert_eq!(c.encrypt(&mut buf, u64::MAX), Err(Error::CounterExhausted));
+        assert_eq!(buf, [0u8; 65]);
+
+        let mut buf = [0u8; 64];
+        assert_eq!(c.encrypt(&mut buf, u64::MAX), Ok(()));
+    }
+
+    #[test]
+    fn test_reduced_rounds() {
+        // Keystream vectors for the 8-byte-nonce layout from the c2-chacha test suite.
+        // An IETF nonce of four zero bytes followed by the 8-byte nonce gives the
+        // same state while the counter stays below 2^32.
+        {
+            let key = [
+                0x27, 0xfc, 0x12, 0x0b, 0x01, 0x3b, 0x82, 0x9f, 0x1f, 0xae, 0xef, 0xd1, 0xab, 0x41,
+                0x7e, 0x86, 0x62, 0xf4, 0x3e, 0x0d, 0x73, 0xf9, 0x8d, 0xe8, 0x66, 0xe3, 0x46, 0x35,
+                0x31, 0x80, 0xfd, 0xb7,
+            ];
+            let nonce = [
+                0x00, 0x00, 0x00, 0x00, 0xdb, 0x4b, 0x4a, 0x41, 0xd8, 0xdf, 0x18, 0xaa,
+            ];
+
+            let mut keystream = [0u8; 100];
+
+            let c = ChaCha12::new(key, nonce);
+            c.encrypt(&mut keystream, 0).unwrap();
+
+            let want = [
+                0x5f, 0x3c, 0x8c, 0x19, 0x0a, 0x78, 0xab, 0x7f, 0xe8, 0x08, 0xca, 0xe9, 0xcb, 0xcb,
+                0x0a, 0x98, 0x37, 0xc8, 0x93, 0x49, 0x2d, 0x96, 0x3a, 0x1c, 0x2e, 0xda, 0x6c, 0x15,
+                0x58, 0xb0, 0x2c, 0x83, 0xfc, 0x02, 0xa4, 0x4c, 0xbb, 0xb7, 0xe6, 0x20, 0x4d, 0x51,
+                0xd1, 0xc2, 0x43, 0x0e, 0x9c, 0x0b, 0x58, 0xf2, 0x93, 0x7b, 0xf5, 0x93, 0x84, 0x0c,
+                0x85, 0x0b, 0xda, 0x90, 0x51, 0xa1, 0xf0, 0x51, 0xdd, 0xf0, 0x9d, 0x2a, 0x03, 0xeb,
+                0xf0, 0x9f, 0x01, 0xbd, 0xba, 0x9d, 0xa0, 0xb6, 0xda, 0x79, 0x1b, 0x2e, 0x64, 0x56,
+                0x41, 0x04, 0x7d, 0x11, 0xeb, 0xf8, 0x50, 0x87, 0xd4, 0xde, 0x5c, 0x01, 0x5f, 0xdd,
+                0xd0, 0x44,
+            ];
+
+            assert_eq!(want, keystream)
+        }
+        {
+            let key = [
+                0x64, 0x1a, 0xea, 0xeb, 0x08, 0x03, 0x6b, 0x61, 0x7a, 0x42, 0xcf, 0x14, 0xe8, 0xc5,
+                0xd2, 0xd1, 0x15, 0xf8, 0xd7, 0xcb, 0x6e, 0xa5, 0xe2, 0x8b, 0x9b, 0xfa, 0xf8, 0x3e,
+                0x03, 0x84, 0x26, 0xa7,
+            ];
+            let nonce = [
+                0x00, 0x00, 0x00, 0x00, 0xa1, 0x4a, 0x11, 0x68, 0x27, 0x1d, 0x45, 0x9b,
+            ];
+
+            let mut keystream = [0u8; 100];
+
+            let c = ChaCha8::new(key, nonce);
+            c.encrypt(&mut keystream, 0).unwrap();
+
+            let want = [
+                0x17, 0x21, 0xc0, 0x44, 0xa8, 0xa6, 0x45, 0x35, 0x22, 0xdd, 0xdb, 0x31, 0x43, 0xd0,
+                0xbe, 0x35, 0x12, 0x63, 0x3c, 0xa3, 0xc7, 0x9b, 0xf8, 0xcc, 0xc3, 0x59, 0x4c, 0xb2,
+                0xc2, 0xf3, 0x10, 0xf7, 0xbd, 0x54, 0x4f, 0x55, 0xce, 0x0d, 0xb3, 0x81, 0x23, 0x41,
+                0x2d, 0x6c, 0x45, 0x20, 0x7d, 0x5c, 0xf9, 0xaf, 0x0c, 0x6c, 0x68, 0x0c, 0xce, 0x1f,
+                0x7e, 0x43, 0x38, 0x8d, 0x1b, 0x03, 0x46, 0xb7, 0x13, 0x3c, 0x59, 0xfd, 0x6a, 0xf4,
+                0xa5, 0xa5, 0x68, 0xaa, 0x33, 0x4c, 0xcd, 0xc3, 0x8a, 0xf5, 0xac, 0xe2, 0x01, 0xdf,
+                0x84, 0xd0, 0xa3, 0xca, 0x22, 0x54, 0x94, 0xca, 0x62, 0x09, 0x34, 0x5f, 0xcf, 0x30,
+                0x13, 0x2e,
+            ];
+
+            assert_eq!(want, keystream)
+        }
+        {
+            // State<8> runs the same block function directly
+            let key = [
+                0x64, 0x1a, 0xea, 0xeb, 0x08, 0x03, 0x6b, 0x61, 0x7a, 0x42, 0xcf, 0x14, 0xe8, 0xc5,
+                0xd2, 0xd1, 0x15, 0xf8, 0xd7, 0xcb, 0x6e, 0xa5, 0xe2, 0x8b, 0x9b, 0xfa, 0xf8, 0x3e,
+                0x03, 0x84, 0x26, 0xa7,
+            ];
+            let nonce = [0xa1, 0x4a, 0x11, 0x68, 0x27, 0x1d, 0x45, 0x9b];
+
+            let block = State::<8>::new_legacy(&key, &nonce, 1).block();
+
+            let want = [
+                0x17, 0x21, 0xc0, 0x44, 0xa8, 0xa6, 0x45, 0x35, 0x22, 0xdd, 0xdb, 0x31, 0x43, 0xd0,
+                0xbe, 0x35, 0x12, 0x63, 0x3c, 0xa3, 0xc7, 0x9b, 0xf8, 0xcc, 0xc3, 0x59, 0x4c, 0xb2,
+                0xc2, 0xf3, 0x10, 0xf7, 0xbd, 0x54, 0x4f, 0x55, 0xce, 0x0d, 0xb3, 0x81, 0x23, 0x41,
+                0x2d, 0x6c, 0x45, 0x20, 0x7d, 0x5c, 0xf9, 0xaf, 0x0c, 0x6c, 0x68, 0x0c, 0xce, 0x1f,
+                0x7e, 0x43, 0x38, 0x8d, 0x1b, 0x03, 0x46, 0xb7, 0x13, 0x3c, 0x59, 0xfd, 0x6a, 0xf4,
+                0xa5, 0xa5, 0x68, 0xaa, 0x33, 0x4c, 0xcd, 0xc3, 0x8a, 0xf5, 0xac, 0xe2, 0x01, 0xdf,
+                0x84, 0xd0, 0xa3, 0xca, 0x22, 0x54, 0x94, 0xca, 0x62, 0x09, 0x34, 0x5f, 0xcf, 0x30,
+                0x13, 0x2e,
+            ];
+
+            assert_eq!(want[64..], block[..36])
+        }
+    }
+
+    #[test]
+    fn test_apply_keystream_uneven() {
+        let key = [0x5a; 32];
+        let nonce = [0xa5; 12];
+
+        let mut want = [0u8; 1000];
+        ChaCha20::new(key, nonce).encrypt(&mut want, 0).unwrap();
+
+        for sizes in [
+            &[1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144, 233][..],
+            &[63, 1, 64, 65, 127, 129],
+            &[64, 64, 64],
+            &[0, 200, 0, 7],
+        ] {
+            let mut c = ChaCha20::new(key, nonce);
+            let mut got = [0u8; 1000];
+            let mut rest = &mut got[..];
+            for &n in sizes {
+                let (head, tail) = rest.split_at_mut(n);
+                c.apply_keystream(head).unwrap();
+                rest = tail;
+            }
+            c.apply_keystream(rest).unwrap();
+
+            assert_eq!(got, want, "sizes={sizes:?}");
+            assert_eq!(c.current_pos(), 1000);
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        let key = [0x5a; 32];
+        let nonce = [0xa5; 12];
+
+        let mut want = [0u8; 1000];
+        ChaCha20::new(key, nonce).encrypt(&mut want, 0).unwrap();
+
+        let mut c = ChaCha20::new(key, nonce);
+        for (pos, len) in [
+            (0, 10),
+            (1, 63),
+            (63, 2),
+            (64, 64),
+            (100, 300),
+            (999, 1),
+            (5, 5),
+        ] {
+            c.seek(pos).unwrap();
+            assert_eq!(c.current_pos(), pos);
+
+            let mut buf = vec![0u8; len];
+            c.apply_keystream(&mut buf[..len / 2]).unwrap();
+            c.apply_keystream(&mut buf[len / 2..]).unwrap();
+            assert_eq!(buf, want[pos as usize..pos as usize + len]);
+            assert_eq!(c.current_pos(), pos + len as u64);
+        }
+    }
+
+    #[test]
+    fn test_write_keystream() {
+        // RFC 8439 Appendix A.1 test vectors #1 and #2: blocks 0 and 1 under an
+        // all-zero key and nonce
+        let want = [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+            0xbd, 0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc,
+            0x8b, 0x77, 0x0d, 0xc7, 0xda, 0x41, 0x59, 0x7c, 0x51, 0x57, 0x48, 0x8d, 0x77, 0x24,
+            0xe0, 0x3f, 0xb8, 0xd8, 0x4a, 0x37, 0x6a, 0x43, 0xb8, 0xf4, 0x15, 0x18, 0xa1, 0x1c,
+            0xc3, 0x87, 0xb6, 0x69, 0xb2, 0xee, 0x65, 0x86, 0x9f, 0x07, 0xe7, 0xbe, 0x55, 0x51,
+            0x38, 0x7a, 0x98, 0xba, 0x97, 0x7c, 0x73, 0x2d, 0x08, 0x0d, 0xcb, 0x0f, 0x29, 0xa0,
+            0x48, 0xe3, 0x65, 0x69, 0x12, 0xc6, 0x53, 0x3e, 0x32, 0xee, 0x7a, 0xed, 0x29, 0xb7,
+            0x21, 0x76, 0x9c, 0xe6, 0x4e, 0x43, 0xd5, 0x71, 0x33, 0xb0, 0x74, 0xd8, 0x39, 0xd5,
+            0x31, 0xed, 0x1f, 0x28, 0x51, 0x0a, 0xfb, 0x45, 0xac, 0xe1, 0x0a, 0x1f, 0x4b, 0x79,
+            0x4d, 0x6f,
+        ];
+
+        let mut c = ChaCha20::new([0u8; 32], [0u8; 12]);
+        let mut got = [0xffu8; 128];
+        let (a, b) = got.split_at_mut(37);
+        c.write_keystream(a).unwrap();
+        c.write_keystream(b).unwrap();
+        assert_eq!(got, want);
+        assert_eq!(c.current_pos(), 128);
+
+        c.seek(10).unwrap();
+        assert_eq!(c.blocks().next().unwrap(), want[10..74]);
+        assert_eq!(c.current_pos(), 74);
+    }
+
+    #[test]
+    fn test_keystream_blocks() {
+        let mut c = ChaCha20::new([0x3c; 32], [0xc3; 12]);
+        let blocks: Vec<[u8; 64]> = c.blocks().take(3).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            assert_eq!(*block, c.keystream_block(i as u32));
+        }
+        assert_eq!(c.current_pos(), 3 * 64);
+
+        // Iteration stops once a whole block no longer fits
+        c.seek(MAX_POS - 100).unwrap();
+        {
+            let mut blocks = c.blocks();
+            assert!(blocks.next().is_some());
+            assert_eq!(blocks.next(), None);
+        }
+        assert_eq!(c.current_pos(), MAX_POS - 36);
+    }
+
+    #[test]
+    fn test_seek_limit() {
+        let mut c = ChaCha20::new([0u8; 32], [0u8; 12]);
+
+        assert_eq!(c.seek(MAX_POS + 1), Err(Error::CounterExhausted));
+
+        c.seek(MAX_POS - 10).unwrap();
+        let mut buf = [0u8; 11];
+        assert_eq!(c.apply_keystream(&mut buf), Err(Error::CounterExhausted));
+        assert_eq!(buf, [0u8; 11]);
+        assert_eq!(c.current_pos(), MAX_POS - 10);
+
+        c.apply_keystream(&mut buf[..10]).unwrap();
+        assert_eq!(c.current_pos(), MAX_POS);
+
+        let mut want = [0u8; 64];
+        ChaCha20::new([0u8; 32], [0u8; 12])
+            .encrypt(&mut want, u32::MAX)
+            .unwrap();
+        assert_eq!(buf[..10], want[54..]);
+
+        assert_eq!(c.apply_keystream(&mut []), Ok(()));
+        assert_eq!(c.apply_keystream(&mut [0u8]), Err(Error::CounterExhausted));
+    }
+
+    #[cfg(feature = "cipher")]
+    #[test]
+    fn test_cipher_traits() {
+        use cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+
+        let key = [0x5a; 32];
+        let nonce = [0xa5; 12];
+
+        let mut want = [0u8; 1000];
+        ChaCha20::new(key, nonce).encrypt(&mut want, 0).unwrap();
+
+        // Separate input and output buffers, split at uneven points
+        let mut c = <ChaCha20 as KeyIvInit>::new(&key.into(), &nonce.into());
+        let input = [0u8; 1000];
+        let mut got = [0u8; 1000];
+        for (i, o) in input.chunks(333).zip(got.chunks_mut(333)) {
+            c.apply_keystream_b2b(i, o).unwrap();
+        }
+        assert_eq!(got, want);
+        assert_eq!(c.try_current_pos::<u64>().unwrap(), 1000);
+
+        StreamCipherSeek::seek(&mut c, 130u32);
+        let mut buf = [0u8; 70];
+        StreamCipher::apply_keystream(&mut c, &mut buf);
+        assert_eq!(buf, want[130..200]);
+
+        // Positions past i32::MAX do not fit in an i32
+        c.try_seek(1u64 << 31).unwrap();
+        assert!(c.try_current_pos::<i32>().is_err());
+        assert_eq!(c.try_current_pos::<u32>().unwrap(), 1 << 31);
+
+        // Running past the last block is an error and leaves the buffer alone
+        c.try_seek(MAX_POS - 10).unwrap();
+        let mut buf = [0u8; 11];
+        assert!(c.try_apply_keystream(&mut buf).is_err());
+        assert_eq!(buf, [0u8; 11]);
+        assert!(c.try_apply_keystream(&mut buf[..10]).is_ok());
+        assert_eq!(c.try_current_pos::<u64>().unwrap(), MAX_POS);
+
+        assert!(c.try_seek(MAX_POS + 1).is_err());
+        assert!(c.try_seek(u128::MAX).is_err());
+    }
+
+    fn encrypt_scalar<const ROUNDS: usize>(c: &ChaCha<ROUNDS>, buf: &mut [u8], counter: u32) {
+        for (i, chunk) in buf.chunks_mut(64).enumerate() {
+            let block =
+                State::<ROUNDS>::from_u32(&c.key_u32, &c.nonce_u32, counter.wrapping_add(i as u32))
+                    .block();
+            xor_in_place(chunk, &block);
+        }
+    }
+
+    #[test]
+    fn test_encrypt_matches_scalar() {
+        fn check<const ROUNDS: usize>() {
+            let c = ChaCha::<ROUNDS>::new([0x3c; 32], [0xc3; 12]);
+            for len in [0, 63, 64, 255, 256, 257, 511, 512, 513, 1000, 1024, 1500] {
+                for counter in [0, 1, 7, u32::MAX - 30] {
+                    let mut got: Vec<u8> = (0..len).map(|i| i as u8).collect();
+                    let mut want = got.clone();
+
+                    c.encrypt(&mut got, counter).unwrap();
+                    encrypt_scalar(&c, &mut want, counter);
+
+                    assert_eq!(got, want, "rounds={ROUNDS} len={len} counter={counter}");
+                }
+            }
+        }
+        check::<20>();
+        check::<12>();
+        check::<8>();
+    }
+
+    #[test]
+    fn test_wide_block() {
+        fn check<const ROUNDS: usize>(counter: u32) {
+            let state = State::<ROUNDS>::new(&[0x3c; 32], &[0xc3; 12], counter);
+
+            let mut want = [0u8; 64 * portable::BLOCKS];
+            for (i, chunk) in want.chunks_exact_mut(64).enumerate() {
+                let mut s = state.clone();
+                s.x[12] = s.x[12].wrapping_add(i as u32);
+                chunk.copy_from_slice(&s.block());
+            }
+
+            assert_eq!(
+                state.wide_block(),
+                want,
+                "rounds={ROUNDS} counter={counter}"
+            );
+        }
+        for counter in [0, 1, 0x7fffffff, u32::MAX - 1] {
+            check::<20>(counter);
+            check::<12>(counter);
+            check::<8>(counter);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_simd_blocks() {
+        fn check<const ROUNDS: usize>(counter: u32) {
+            let state = State::<ROUNDS>::new(&[0x3c; 32], &[0xc3; 12], counter);
+
+            let mut want = [0u8; 64 * 8];
+            for (i, chunk) in want.chunks_exact_mut(64).enumerate() {
+                let mut s = state.clone();
+                s.x[12] = s.x[12].wrapping_add(i as u32);
+                chunk.copy_from_slice(&s.block());
+            }
+
+            let mut got = [0u8; 64 * sse2::BLOCKS];
+            // SAFETY: SSE2 is part of the x86_64 baseline
+            unsafe { sse2::blocks::<ROUNDS>(&state.x, &mut got) };
+            assert_eq!(
+                got,
+                want[..got.len()],
+                "sse2 rounds={ROUNDS} counter={counter}"
+            );
+
+            if std::is_x86_feature_detected!("avx2") {
+                let mut got = [0u8; 64 * avx2::BLOCKS];
+                // SAFETY: AVX2 support was detected at runtime
+                unsafe { avx2::blocks::<ROUNDS>(&state.x, &mut got) };
+                assert_eq!(
+                    got,
+                    want[..got.len()],
+                    "avx2 rounds={ROUNDS} counter={counter}"
+                );
+            }
+        }
+        for counter in [0, 1, 0x7fffffff, u32::MAX - 3] {
+            check::<20>(counter);
+            check::<12>(counter);
+            check::<8>(counter);
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize_on_drop() {
+        use core::mem::MaybeUninit;
+        use core::ptr;
+
+        // 300 bytes go through the multi-block backend, which wipes its
+        // temporaries only after the keystream has been stored
+        let mut c = ChaCha20::new([0x42; 32], [0x24; 12]);
+        let mut buf = [0u8; 300];
+        c.apply_keystream(&mut buf).unwrap();
+        for (i, chunk) in buf.chunks(64).enumerate() {
+            assert_eq!(chunk, &c.keystream_block(i as u32)[..chunk.len()]);
+        }
+        assert_eq!(c.current_pos(), 300);
+
+        let mut c = MaybeUninit::new(c);
+        let mut s = MaybeUninit::new(State::<20>::new(&[0x42; 32], &[0x24; 12], 1));
+        // SAFETY: both values are initialized and dropped exactly once here;
+        // MaybeUninit never drops them again
+        unsafe {
+            ptr::drop_in_place(c.as_mut_ptr());
+            ptr::drop_in_place(s.as_mut_ptr());
+        }
+
+        let (c, s) = (c.as_ptr(), s.as_ptr());
+        // SAFETY: dropping leaves the memory in place, and only plain-data
+        // fields are read, through raw pointers rather than references
+        unsafe {
+            assert_eq!((&raw const (*c).key_u32).read(), [0u32; 8]);
+            assert_eq!((&raw const (*c).nonce_u32).read(), [0u32; 3]);
+            assert_eq!((&raw const (*c).buffer).read(), [0u8; 64]);
+            assert_eq!((&raw const (*s).x).read(), [0u32; 16]);
+        }
+    }
 }
@@ -0,0 +1,104 @@
+//! AVX2 backend computing eight blocks at once.
+//!
+//! Same layout as the SSE2 backend with 256-bit vectors: each `__m256i` holds
+//! one state word for eight consecutive blocks.
+
+use core::arch::x86_64::*;
+
+use crate::wipe;
+
+pub(super) const BLOCKS: usize = 8;
+
+/// Detects AVX2 at runtime with `std`, otherwise only when the crate is built
+/// with the `avx2` target feature.
+pub(super) fn available() -> bool {
+    #[cfg(feature = "std")]
+    {
+        std::is_x86_feature_detected!("avx2")
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        cfg!(target_feature = "avx2")
+    }
+}
+
+macro_rules! rotl {
+    ($x:expr, $n:literal) => {
+        _mm256_or_si256(
+            _mm256_slli_epi32::<$n>($x),
+            _mm256_srli_epi32::<{ 32 - $n }>($x),
+        )
+    };
+}
+
+#[target_feature(enable = "avx2")]
+fn quarter_round(v: &mut [__m256i; 16], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = _mm256_add_epi32(v[a], v[b]);
+    v[d] = rotl!(_mm256_xor_si256(v[d], v[a]), 16);
+
+    v[c] = _mm256_add_epi32(v[c], v[d]);
+    v[b] = rotl!(_mm256_xor_si256(v[b], v[c]), 12);
+
+    v[a] = _mm256_add_epi32(v[a], v[b]);
+    v[d] = rotl!(_mm256_xor_si256(v[d], v[a]), 8);
+
+    v[c] = _mm256_add_epi32(v[c], v[d]);
+    v[b] = rotl!(_mm256_xor_si256(v[b], v[c]), 7);
+}
+
+/// Writes the keystream of blocks `x[12]` to `x[12] + 7` into `out`.
+#[target_feature(enable = "avx2")]
+pub(super) fn blocks<const ROUNDS: usize>(x: &[u32; 16], out: &mut [u8; 64 * BLOCKS]) {
+    let mut init = x.map(|w| _mm256_set1_epi32(w as i32));
+    init[12] = _mm256_add_epi32(init[12], _mm256_set_epi32(7, 6, 5, 4, 3, 2, 1, 0));
+
+    let mut v = init;
+    for _ in 0..ROUNDS / 2 {
+        // column rounds
+        quarter_round(&mut v, 0, 4, 8, 12);
+        quarter_round(&mut v, 1, 5, 9, 13);
+        quarter_round(&mut v, 2, 6, 10, 14);
+        quarter_round(&mut v, 3, 7, 11, 15);
+
+        // diagonal rounds
+        quarter_round(&mut v, 0, 5, 10, 15);
+        quarter_round(&mut v, 1, 6, 11, 12);
+        quarter_round(&mut v, 2, 7, 8, 13);
+        quarter_round(&mut v, 3, 4, 9, 14);
+    }
+    for i in 0..16 {
+        v[i] = _mm256_add_epi32(v[i], init[i]);
+    }
+
+    // The unpacks work within 128-bit halves, so the low half of each row
+    // belongs to block n and the high half to block n + 4
+    for i in (0..16).step_by(4) {
+        let mut t = [
+            _mm256_unpacklo_epi32(v[i], v[i + 1]),
+            _mm256_unpackhi_epi32(v[i], v[i + 1]),
+            _mm256_unpacklo_epi32(v[i + 2], v[i + 3]),
+            _mm256_unpackhi_epi32(v[i + 2], v[i + 3]),
+        ];
+        let mut rows = [
+            _mm256_unpacklo_epi64(t[0], t[2]),
+            _mm256_unpackhi_epi64(t[0], t[2]),
+            _mm256_unpacklo_epi64(t[1], t[3]),
+            _mm256_unpackhi_epi64(t[1], t[3]),
+        ];
+        for (block, &row) in rows.iter().enumerate() {
+            let halves = [
+                (block, _mm256_castsi256_si128(row)),
+                (block + 4, _mm256_extracti128_si256::<1>(row)),
+            ];
+            for (block, half) in halves {
+                let dst = &mut out[64 * block + 4 * i..64 * block + 4 * i + 16];
+                // SAFETY: `dst` is exactly 16 bytes and the store is unaligned
+                unsafe { _mm_storeu_si128(dst.as_mut_ptr().cast(), half) };
+            }
+        }
+        wipe(&mut t);
+        wipe(&mut rows);
+    }
+    wipe(&mut init);
+    wipe(&mut v);
+}
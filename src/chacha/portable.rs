@@ -0,0 +1,90 @@
+//! Portable backend built from `[u32; N]` lane arrays.
+//!
+//! Every step is a plain loop over the lanes, which LLVM turns into vector
+//! instructions where the target has them, without intrinsics or `unsafe`.
+
+use crate::wipe;
+
+pub(super) const BLOCKS: usize = 4;
+
+fn add<const N: usize>(a: &mut [u32; N], b: &[u32; N]) {
+    for (x, y) in a.iter_mut().zip(b) {
+        *x = x.wrapping_add(*y);
+    }
+}
+
+fn xor_rotl<const N: usize>(a: &mut [u32; N], b: &[u32; N], n: u32) {
+    for (x, y) in a.iter_mut().zip(b) {
+        *x = super::rotation_n(n, *x ^ y);
+    }
+}
+
+pub(super) fn quarter_round<const N: usize>(
+    a: &mut [u32; N],
+    b: &mut [u32; N],
+    c: &mut [u32; N],
+    d: &mut [u32; N],
+) {
+    add(a, b);
+    xor_rotl(d, a, 16);
+
+    add(c, d);
+    xor_rotl(b, c, 12);
+
+    add(a, b);
+    xor_rotl(d, a, 8);
+
+    add(c, d);
+    xor_rotl(b, c, 7);
+}
+
+/// Runs a column round and a diagonal round over a state stored as four rows
+/// of four words, each word spread over `N / 4` lanes.
+///
+/// Rotating rows b, c and d by one, two and three words lines the diagonals
+/// up as columns, so both rounds are the same lane-wise quarter round.
+pub(super) fn double_round<const N: usize>(rows: &mut [[u32; N]; 4]) {
+    let w = N / 4;
+    let [a, b, c, d] = rows;
+
+    // column rounds
+    quarter_round(a, b, c, d);
+
+    // diagonal rounds
+    b.rotate_left(w);
+    c.rotate_left(2 * w);
+    d.rotate_left(3 * w);
+    quarter_round(a, b, c, d);
+    b.rotate_right(w);
+    c.rotate_right(2 * w);
+    d.rotate_right(3 * w);
+}
+
+/// Writes the keystream of blocks `x[12]` to `x[12] + 3` into `out`.
+pub(super) fn blocks<const ROUNDS: usize>(x: &[u32; 16], out: &mut [u8; 64 * BLOCKS]) {
+    // Word i of block n lives in row i / 4 at lane (i % 4) * BLOCKS + n
+    let mut init = [[0u32; 4 * BLOCKS]; 4];
+    for (i, &w) in x.iter().enumerate() {
+        init[i / 4][(i % 4) * BLOCKS..][..BLOCKS].fill(w);
+    }
+    for (n, w) in init[3][..BLOCKS].iter_mut().enumerate() {
+        *w = x[12].wrapping_add(n as u32);
+    }
+
+    let mut rows = init;
+    for _ in 0..ROUNDS / 2 {
+        double_round(&mut rows);
+    }
+    for (row, init) in rows.iter_mut().zip(&init) {
+        add(row, init);
+    }
+
+    for i in 0..16 {
+        for n in 0..BLOCKS {
+            let w = rows[i / 4][(i % 4) * BLOCKS + n];
+            out[64 * n + 4 * i..][..4].copy_from_slice(&w.to_le_bytes());
+        }
+    }
+    wipe(&mut init);
+    wipe(&mut rows);
+}
@@ -0,0 +1,82 @@
+//! SSE2 backend computing four blocks at once.
+//!
+//! Each `__m128i` holds one state word for four consecutive blocks, so the
+//! quarter rounds run on all four blocks in parallel with the same word
+//! indices as [`State::inner_block`](super::State).
+
+use core::arch::x86_64::*;
+
+use crate::wipe;
+
+pub(super) const BLOCKS: usize = 4;
+
+macro_rules! rotl {
+    ($x:expr, $n:literal) => {
+        _mm_or_si128(_mm_slli_epi32::<$n>($x), _mm_srli_epi32::<{ 32 - $n }>($x))
+    };
+}
+
+#[target_feature(enable = "sse2")]
+fn quarter_round(v: &mut [__m128i; 16], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = _mm_add_epi32(v[a], v[b]);
+    v[d] = rotl!(_mm_xor_si128(v[d], v[a]), 16);
+
+    v[c] = _mm_add_epi32(v[c], v[d]);
+    v[b] = rotl!(_mm_xor_si128(v[b], v[c]), 12);
+
+    v[a] = _mm_add_epi32(v[a], v[b]);
+    v[d] = rotl!(_mm_xor_si128(v[d], v[a]), 8);
+
+    v[c] = _mm_add_epi32(v[c], v[d]);
+    v[b] = rotl!(_mm_xor_si128(v[b], v[c]), 7);
+}
+
+/// Writes the keystream of blocks `x[12]` to `x[12] + 3` into `out`.
+#[target_feature(enable = "sse2")]
+pub(super) fn blocks<const ROUNDS: usize>(x: &[u32; 16], out: &mut [u8; 64 * BLOCKS]) {
+    let mut init = x.map(|w| _mm_set1_epi32(w as i32));
+    init[12] = _mm_add_epi32(init[12], _mm_set_epi32(3, 2, 1, 0));
+
+    let mut v = init;
+    for _ in 0..ROUNDS / 2 {
+        // column rounds
+        quarter_round(&mut v, 0, 4, 8, 12);
+        quarter_round(&mut v, 1, 5, 9, 13);
+        quarter_round(&mut v, 2, 6, 10, 14);
+        quarter_round(&mut v, 3, 7, 11, 15);
+
+        // diagonal rounds
+        quarter_round(&mut v, 0, 5, 10, 15);
+        quarter_round(&mut v, 1, 6, 11, 12);
+        quarter_round(&mut v, 2, 7, 8, 13);
+        quarter_round(&mut v, 3, 4, 9, 14);
+    }
+    for i in 0..16 {
+        v[i] = _mm_add_epi32(v[i], init[i]);
+    }
+
+    // Transpose each group of four words so every vector holds one block's words
+    for i in (0..16).step_by(4) {
+        let mut t = [
+            _mm_unpacklo_epi32(v[i], v[i + 1]),
+            _mm_unpackhi_epi32(v[i], v[i + 1]),
+            _mm_unpacklo_epi32(v[i + 2], v[i + 3]),
+            _mm_unpackhi_epi32(v[i + 2], v[i + 3]),
+        ];
+        let mut rows = [
+            _mm_unpacklo_epi64(t[0], t[2]),
+            _mm_unpackhi_epi64(t[0], t[2]),
+            _mm_unpacklo_epi64(t[1], t[3]),
+            _mm_unpackhi_epi64(t[1], t[3]),
+        ];
+        for (block, &row) in rows.iter().enumerate() {
+            let dst = &mut out[64 * block + 4 * i..64 * block + 4 * i + 16];
+            // SAFETY: `dst` is exactly 16 bytes and the store is unaligned
+            unsafe { _mm_storeu_si128(dst.as_mut_ptr().cast(), row) };
+        }
+        wipe(&mut t);
+        wipe(&mut rows);
+    }
+    wipe(&mut init);
+    wipe(&mut v);
+}
@@ -0,0 +1,34 @@
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The authentication tag did not match the message.
+    TagMismatch,
+    /// A key, nonce or tag slice had the wrong length.
+    InvalidLength,
+    /// The 32-bit block counter would run past its last value.
+    CounterExhausted,
+    /// An output buffer is too small to hold the result.
+    BufferTooSmall,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TagMismatch => f.write_str("authentication tag mismatch"),
+            Error::InvalidLength => f.write_str("invalid length"),
+            Error::CounterExhausted => f.write_str("block counter exhausted"),
+            Error::BufferTooSmall => f.write_str("buffer too small"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "aead")]
+impl From<Error> for aead::Error {
+    fn from(_: Error) -> Self {
+        aead::Error
+    }
+}
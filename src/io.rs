@@ -0,0 +1,593 @@
+//! `std::io` adapters that encrypt or decrypt while data is copied.
+//!
+//! [`EncryptingWriter`] and [`DecryptingReader`] apply the ChaCha20 keystream
+//! and add no overhead, but nothing detects tampering. [`SealingWriter`] and
+//! [`OpeningReader`] instead cut the data into [`CHUNK_LEN`]-byte segments
+//! sealed with [`StreamEncryptor`], each followed by its 16-byte tag.
+
+use std::io::{self, Read, Write};
+use std::{vec, vec::Vec};
+
+use crate::aead::TAG_LEN;
+use crate::chacha::{ChaCha20, MAX_POS};
+use crate::stream::{StreamDecryptor, StreamEncryptor};
+
+/// Plaintext bytes in every segment but the last, which is always shorter.
+pub const CHUNK_LEN: usize = 16 * 1024;
+
+fn io_error(e: crate::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Encrypts everything written to it with ChaCha20 before passing it on.
+///
+/// Writes continue the keystream from the cipher's current position. After an
+/// error the position may be ahead of what reached `inner`, so the writer
+/// should be discarded.
+pub struct EncryptingWriter<W: Write> {
+    inner: W,
+    cipher: ChaCha20,
+    scratch: [u8; 4096],
+}
+
+impl<W: Write> EncryptingWriter<W> {
+    pub fn new(inner: W, cipher: ChaCha20) -> Self {
+        EncryptingWriter {
+            inner,
+            cipher,
+            scratch: [0u8; 4096],
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for EncryptingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(self.scratch.len());
+        let chunk = &mut self.scratch[..n];
+        chunk.copy_from_slice(&data[..n]);
+        self.cipher.apply_keystream(chunk).map_err(io_error)?;
+        self.inner.write_all(chunk)?;
+        crate::wipe(chunk);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts ChaCha20 ciphertext as it is read from `inner`.
+///
+/// Reads stop at the end of the keystream: once it is used up, `read` fails
+/// without taking anything from `inner`.
+pub struct DecryptingReader<R: Read> {
+    inner: R,
+    cipher: ChaCha20,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    pub fn new(inner: R, cipher: ChaCha20) -> Self {
+        DecryptingReader { inner, cipher }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Only take from `inner` what the remaining keystream can decrypt
+        let left = MAX_POS - self.cipher.current_pos();
+        if left == 0 && !buf.is_empty() {
+            return Err(io_error(crate::Error::CounterExhausted));
+        }
+        let len = (buf.len() as u64).min(left) as usize;
+        let buf = &mut buf[..len];
+
+        let n = self.inner.read(buf)?;
+        self.cipher
+            .apply_keystream(&mut buf[..n])
+            .map_err(io_error)?;
+        Ok(n)
+    }
+}
+
+/// Seals data in [`CHUNK_LEN`]-byte segments as it is written.
+///
+/// [`finish`](Self::finish) must be called to seal the last segment; a stream
+/// without it is rejected by [`OpeningReader`] as truncated, and data still
+/// buffered when the writer is dropped is lost.
+///
+/// Errors from `inner`, such as `WouldBlock`, can be retried: a sealed segment
+/// that was not fully written is finished by the next call.
+pub struct SealingWriter<W: Write> {
+    inner: W,
+    enc: StreamEncryptor,
+    // Plaintext of the segment being collected
+    buf: Vec<u8>,
+    // Sealed segment waiting to be written, of which out[..out_pos] already is
+    out: Vec<u8>,
+    out_pos: usize,
+}
+
+impl<W: Write> SealingWriter<W> {
+    pub fn new(inner: W, enc: StreamEncryptor) -> Self {
+        SealingWriter {
+            inner,
+            enc,
+            buf: Vec::with_capacity(CHUNK_LEN + TAG_LEN),
+            out: Vec::with_capacity(CHUNK_LEN + TAG_LEN),
+            out_pos: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Seals the buffered data as the last segment, writes it out and returns
+    /// the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_sealed()?;
+        let tag = self
+            .enc
+            .encrypt_last(&mut self.buf, b"")
+            .map_err(io_error)?;
+        self.buf.extend_from_slice(&tag);
+        self.inner.write_all(&self.buf)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Writes out the pending sealed segment, then seals `buf` if it is full
+    /// and writes that out too.
+    fn write_sealed(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        if self.buf.len() == CHUNK_LEN {
+            let tag = self
+                .enc
+                .encrypt_next(&mut self.buf, b"")
+                .map_err(io_error)?;
+            self.buf.extend_from_slice(&tag);
+            core::mem::swap(&mut self.buf, &mut self.out);
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    /// Writes out the rest of the pending sealed segment.
+    fn write_pending(&mut self) -> io::Result<()> {
+        while self.out_pos < self.out.len() {
+            match self.inner.write(&self.out[self.out_pos..]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => self.out_pos += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.out.clear();
+        self.out_pos = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for SealingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.write_sealed()?;
+
+        let n = data.len().min(CHUNK_LEN - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    /// Writes out every full segment and flushes `inner`. A partial segment
+    /// stays buffered until it fills up or [`finish`](Self::finish) is called.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_sealed()?;
+        self.inner.flush()
+    }
+}
+
+/// Reads and opens the segments written by a [`SealingWriter`].
+///
+/// Plaintext is only returned once its whole segment has been authenticated.
+/// End of file is reported only after the last segment has been verified, so
+/// truncated, reordered or tampered streams give an error instead.
+///
+/// Errors from `inner`, such as `WouldBlock`, can be retried: the part of the
+/// segment read so far is kept. Once a segment fails to open, every later read
+/// fails too.
+pub struct OpeningReader<R: Read> {
+    inner: R,
+    // None once the last segment has been opened, or after a failure
+    dec: Option<StreamDecryptor>,
+    done: bool,
+    // Ciphertext of the next segment while it is read, then its plaintext
+    buf: Vec<u8>,
+    // Ciphertext bytes of the next segment read so far
+    filled: usize,
+    // Unread plaintext of the current segment is buf[pos..end]
+    pos: usize,
+    end: usize,
+}
+
+impl<R: Read> OpeningReader<R> {
+    pub fn new(inner: R, dec: StreamDecryptor) -> Self {
+        OpeningReader {
+            inner,
+            dec: Some(dec),
+            done: false,
+            buf: vec![0u8; CHUNK_LEN + TAG_LEN],
+            filled: 0,
+            pos: 0,
+            end: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Reads and opens the next segment into `buf`.
+    fn next_segment(&mut self) -> io::Result<()> {
+        if self.dec.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream failed to open earlier",
+            ));
+        }
+
+        // Fill a whole segment; a short one can only be the last
+        while self.filled < self.buf.len() {
+            match self.inner.read(&mut self.buf[self.filled..]) {
+                Ok(0) => break,
+                Ok(m) => self.filled += m,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let n = core::mem::take(&mut self.filled);
+        let mut dec = self.dec.take().unwrap();
+        if n < TAG_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream truncated",
+            ));
+        }
+
+        let (ciphertext, tag) = self.buf[..n].split_at_mut(n - TAG_LEN);
+        let tag: [u8; TAG_LEN] = (*tag).try_into().unwrap();
+        let next = if n == CHUNK_LEN + TAG_LEN {
+            dec.decrypt_next(ciphertext, b"", tag).map(|()| Some(dec))
+        } else {
+            dec.decrypt_last(ciphertext, b"", tag).map(|()| None)
+        }
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.done = next.is_none();
+        self.dec = next;
+        self.pos = 0;
+        self.end = n - TAG_LEN;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for OpeningReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.end {
+            if self.done || out.is_empty() {
+                return Ok(0);
+            }
+            self.next_segment()?;
+        }
+
+        let n = out.len().min(self.end - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const NONCE: [u8; 12] = [0x24; 12];
+    const PREFIX: [u8; 7] = [0x07; 7];
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7) as u8).collect()
+    }
+
+    /// Reader that returns at most `max` bytes per call.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        max: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.max).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    /// Moves at most `max` bytes per call and, while `stalling`, fails every
+    /// other call with `WouldBlock` like a non-blocking stream that is not ready.
+    struct Stalling<T> {
+        inner: T,
+        max: usize,
+        stalling: bool,
+        ready: bool,
+    }
+
+    impl<T> Stalling<T> {
+        fn new(inner: T, max: usize) -> Self {
+            Stalling {
+                inner,
+                max,
+                stalling: true,
+                ready: false,
+            }
+        }
+
+        fn stall(&mut self) -> io::Result<()> {
+            if self.stalling {
+                self.ready = !self.ready;
+                if !self.ready {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<T: Read> Read for Stalling<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.stall()?;
+            let n = buf.len().min(self.max);
+            self.inner.read(&mut buf[..n])
+        }
+    }
+
+    impl<T: Write> Write for Stalling<T> {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.stall()?;
+            self.inner.write(&data[..data.len().min(self.max)])
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    fn seal(plaintext: &[u8]) -> Vec<u8> {
+        let mut w = SealingWriter::new(Vec::new(), StreamEncryptor::new(KEY, PREFIX));
+        io::copy(
+            &mut Trickle {
+                data: plaintext,
+                max: 1000,
+            },
+            &mut w,
+        )
+        .unwrap();
+        w.finish().unwrap()
+    }
+
+    fn open(sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut r = OpeningReader::new(
+            Trickle {
+                data: sealed,
+                max: 777,
+            },
+            StreamDecryptor::new(KEY, PREFIX),
+        );
+        let mut out = Vec::new();
+        io::copy(&mut r, &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_keystream_adapters() {
+        let plaintext = data(10_000);
+
+        let mut want = plaintext.clone();
+        ChaCha20::new(KEY, NONCE).encrypt(&mut want, 0).unwrap();
+
+        let mut w = EncryptingWriter::new(Vec::new(), ChaCha20::new(KEY, NONCE));
+        io::copy(
+            &mut Trickle {
+                data: &plaintext,
+                max: 333,
+            },
+            &mut w,
+        )
+        .unwrap();
+        let ciphertext = w.into_inner();
+        assert_eq!(ciphertext, want);
+
+        let mut r = DecryptingReader::new(&ciphertext[..], ChaCha20::new(KEY, NONCE));
+        let mut got = Vec::new();
+        r.read_to_end(&mut got).unwrap();
+        assert_eq!(got, plaintext);
+    }
+
+    #[test]
+    fn test_decrypting_keystream_end() {
+        let mut c = ChaCha20::new(KEY, NONCE);
+        c.seek(MAX_POS - 10).unwrap();
+        let mut ciphertext = data(10);
+        c.clone().apply_keystream(&mut ciphertext).unwrap();
+        ciphertext.extend_from_slice(&[0xee; 20]);
+
+        // Only the 10 bytes the keystream covers are taken from `inner`
+        let mut r = DecryptingReader::new(&ciphertext[..], c);
+        let mut out = [0u8; 64];
+        assert_eq!(r.read(&mut out).unwrap(), 10);
+        assert_eq!(out[..10], data(10));
+
+        let err = r.read(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(r.read(&mut []).unwrap(), 0);
+        assert_eq!(r.into_inner(), &[0xee; 20]);
+    }
+
+    #[test]
+    fn test_sealed_roundtrip() {
+        for len in [
+            0,
+            1,
+            CHUNK_LEN - 1,
+            CHUNK_LEN,
+            CHUNK_LEN + 1,
+            3 * CHUNK_LEN + 5,
+        ] {
+            let plaintext = data(len);
+            let sealed = seal(&plaintext);
+
+            let segments = len / CHUNK_LEN + 1;
+            assert_eq!(sealed.len(), len + segments * TAG_LEN, "len={len}");
+            assert_eq!(open(&sealed).unwrap(), plaintext, "len={len}");
+        }
+    }
+
+    #[test]
+    fn test_sealed_segments() {
+        // Every segment is a STREAM segment under the same key and prefix
+        let plaintext = data(CHUNK_LEN + 10);
+        let sealed = seal(&plaintext);
+
+        let mut enc = StreamEncryptor::new(KEY, PREFIX);
+        let mut want = plaintext[..CHUNK_LEN].to_vec();
+        let tag = enc.encrypt_next(&mut want, b"").unwrap();
+        want.extend_from_slice(&tag);
+        let mut last = plaintext[CHUNK_LEN..].to_vec();
+        let tag = enc.encrypt_last(&mut last, b"").unwrap();
+        want.extend_from_slice(&last);
+        want.extend_from_slice(&tag);
+
+        assert_eq!(sealed, want);
+    }
+
+    #[test]
+    fn test_sealed_tampering() {
+        let plaintext = data(2 * CHUNK_LEN + 100);
+        let sealed = seal(&plaintext);
+        let segment = CHUNK_LEN + TAG_LEN;
+
+        // Flipped bit
+        let mut bad = sealed.clone();
+        bad[segment + 5] ^= 1;
+        assert_eq!(open(&bad).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Truncated at a segment boundary, mid-segment and inside a tag
+        for cut in [segment, 2 * segment, segment + 100, sealed.len() - 1] {
+            assert!(open(&sealed[..cut]).is_err(), "cut={cut}");
+        }
+        assert_eq!(
+            open(&sealed[..2 * segment]).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        // Segments swapped
+        let mut swapped = sealed[segment..2 * segment].to_vec();
+        swapped.extend_from_slice(&sealed[..segment]);
+        swapped.extend_from_slice(&sealed[2 * segment..]);
+        assert!(open(&swapped).is_err());
+
+        // Appended data after the last segment
+        let mut extended = sealed.clone();
+        extended.extend_from_slice(&[0u8; 50]);
+        assert!(open(&extended).is_err());
+
+        // Plaintext from before the failure is returned, but never after it
+        let mut r = OpeningReader::new(&bad[..], StreamDecryptor::new(KEY, PREFIX));
+        let mut out = vec![0u8; CHUNK_LEN];
+        r.read_exact(&mut out).unwrap();
+        assert_eq!(out, plaintext[..CHUNK_LEN]);
+        assert!(r.read(&mut out).is_err());
+        assert!(r.read(&mut out).is_err());
+    }
+
+    #[test]
+    fn test_opening_retry() {
+        let plaintext = data(2 * CHUNK_LEN + 100);
+        let sealed = seal(&plaintext);
+        let inner = Stalling::new(&sealed[..], 50);
+        let mut r = OpeningReader::new(inner, StreamDecryptor::new(KEY, PREFIX));
+
+        // The first segment stalls after 50 bytes without returning any of them
+        let mut out = [0u8; 64];
+        assert_eq!(
+            r.read(&mut out).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        let mut got = Vec::new();
+        loop {
+            match r.read(&mut out) {
+                Ok(0) => break,
+                Ok(n) => got.extend_from_slice(&out[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{e}"),
+            }
+        }
+        assert_eq!(got, plaintext);
+    }
+
+    #[test]
+    fn test_sealing_retry() {
+        let plaintext = data(2 * CHUNK_LEN + 100);
+        let inner = Stalling::new(Vec::new(), 1000);
+        let mut w = SealingWriter::new(inner, StreamEncryptor::new(KEY, PREFIX));
+
+        // Sealed segments are cut short and stall midway through
+        let mut rest = &plaintext[..];
+        let mut stalls = 0;
+        while !rest.is_empty() {
+            match w.write(rest) {
+                Ok(n) => rest = &rest[n..],
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => stalls += 1,
+                Err(e) => panic!("{e}"),
+            }
+        }
+        assert!(stalls > 0);
+
+        // finish() cannot be retried, so let the last segment through
+        w.inner.stalling = false;
+        let sealed = w.finish().unwrap().inner;
+        assert_eq!(sealed, seal(&plaintext));
+    }
+
+    #[test]
+    fn test_sealing_full_last_segment() {
+        // A full segment followed by finish() still ends with a short one
+        let plaintext = data(CHUNK_LEN);
+        let mut w = SealingWriter::new(Vec::new(), StreamEncryptor::new(KEY, PREFIX));
+        w.write_all(&plaintext).unwrap();
+        w.flush().unwrap();
+        assert_eq!(w.get_ref().len(), CHUNK_LEN + TAG_LEN);
+
+        let sealed = w.finish().unwrap();
+        assert_eq!(sealed.len(), CHUNK_LEN + 2 * TAG_LEN);
+        assert_eq!(open(&sealed).unwrap(), plaintext);
+    }
+}
@@ -1,3 +1,36 @@
+//! ChaCha20, Poly1305 and ChaCha20-Poly1305 without an allocator.
+//!
+//! The crate is `no_std` by default. The `std` feature adds
+//! `std::error::Error` for [`Error`] and the `std::io` adapters in `io`, and
+//! detects AVX2 at runtime with `is_x86_feature_detected!`. Without it AVX2 is
+//! only used when the crate is built with `-C target-feature=+avx2`. The
+//! `alloc` feature adds helpers that return a `Vec`, and the `zeroize` feature
+//! wipes keys, cipher state and temporary keystream from memory once they are
+//! no longer needed.
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
 pub mod aead;
 pub mod chacha;
-mod poly;
+mod error;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod poly;
+pub mod rng;
+pub mod stream;
+
+pub use error::Error;
+
+/// Overwrites a secret temporary with zeros when the `zeroize` feature is on.
+#[cfg(feature = "zeroize")]
+fn wipe<Z: zeroize::Zeroize + ?Sized>(secret: &mut Z) {
+    secret.zeroize();
+}
+
+#[cfg(not(feature = "zeroize"))]
+fn wipe<Z: ?Sized>(_: &mut Z) {}
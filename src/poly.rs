@@ -1,62 +1,438 @@
-use num::BigUint;
+//! Poly1305 one-time authenticator (RFC 8439 Section 2.5).
+//!
+//! A Poly1305 [`Key`] must authenticate **one message only**. Anyone who sees
+//! two tags made with the same key can recover it and forge tags for other
+//! messages. Derive a fresh key per message, for example with
+//! [`generate_key`] from a ChaCha20 key and a unique nonce.
+
+use crate::{Error, chacha, wipe};
+
+/// One-time Poly1305 key: `r` in the first 16 bytes and `s` in the last 16.
+#[derive(Clone)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    pub fn new_from_slice(key: &[u8]) -> Result<Self, Error> {
+        let key: [u8; 32] = key.try_into().map_err(|_| Error::InvalidLength)?;
+        Ok(Key(key))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for Key {
+    fn from(key: [u8; 32]) -> Self {
+        Key(key)
+    }
+}
+
+/// Poly1305 authentication tag.
+///
+/// Equality is checked in constant time, so comparing a computed tag with a
+/// received one does not leak how many leading bytes matched.
+#[derive(Clone, Copy, Debug)]
+pub struct Tag([u8; 16]);
+
+impl Tag {
+    pub fn new_from_slice(tag: &[u8]) -> Result<Self, Error> {
+        let tag: [u8; 16] = tag.try_into().map_err(|_| Error::InvalidLength)?;
+        Ok(Tag(tag))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl From<[u8; 16]> for Tag {
+    fn from(tag: [u8; 16]) -> Self {
+        Tag(tag)
+    }
+}
+
+impl From<Tag> for [u8; 16] {
+    fn from(tag: Tag) -> Self {
+        tag.0
+    }
+}
+
+impl PartialEq for Tag {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Tag {}
+
+/// Computes the tag of `msg` in one call.
+///
+/// `key` must not be used for any other message.
+pub fn mac(key: &Key, msg: &[u8]) -> Tag {
+    let mut p = Poly1305::new(key);
+    p.update(msg);
+    p.finalize()
+}
+
+/// Incremental Poly1305 that accepts the message in arbitrary pieces.
+///
+/// Partial blocks are kept in an internal buffer until enough data arrives, so
+/// feeding a message in several `update` calls gives the same tag as a single
+/// call to [`mac`].
+#[derive(Clone)]
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    s: [u8; 16],
+    buf: [u8; 16],
+    buf_len: usize,
+}
+
+impl Poly1305 {
+    /// Starts a new authenticator. `key` must not be used for any other message.
+    pub fn new(key: &Key) -> Self {
+        let mut s = [0u8; 16];
+        s.copy_from_slice(&key.0[16..]);
+        Poly1305 {
+            r: clamp_r(&key.0),
+            h: [0u32; 5],
+            s,
+            buf: [0u8; 16],
+            buf_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buf_len > 0 {
+            let l = data.len().min(16 - self.buf_len);
+            self.buf[self.buf_len..self.buf_len + l].copy_from_slice(&data[..l]);
+            self.buf_len += l;
+            data = &data[l..];
+
+            if self.buf_len < 16 {
+                return;
+            }
+            compute_block(&mut self.h, &self.r, &self.buf, 1 << 24);
+            self.buf_len = 0;
+        }
+
+        let mut chunks = data.chunks_exact(16);
+        for block in &mut chunks {
+            compute_block(&mut self.h, &self.r, block.try_into().unwrap(), 1 << 24);
+        }
 
-use crate::chacha;
+        let rest = chunks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
 
-const CLAMPER: [u8; 16] = [
-    0x0f, 0xff, 0xff, 0xfc, 0x0f, 0xff, 0xff, 0xfc, 0x0f, 0xff, 0xff, 0xfc, 0x0f, 0xff, 0xff, 0xff,
-];
+    /// Feeds `data` and then zero-pads up to the next 16-byte boundary, as the
+    /// AEAD construction does for the AAD and the ciphertext.
+    pub fn update_padded(&mut self, data: &[u8]) {
+        self.update(data);
+        if self.buf_len > 0 {
+            self.buf[self.buf_len..].fill(0);
+            compute_block(&mut self.h, &self.r, &self.buf, 1 << 24);
+            self.buf_len = 0;
+        }
+    }
 
-pub fn mac(key: [u8; 32], msg: &[u8]) -> [u8; 16] {
-    let p = BigUint::from(1u8);
-    let p = p << 130;
-    let p: BigUint = p - 5u8;
+    pub fn finalize(mut self) -> Tag {
+        if self.buf_len > 0 {
+            // The trailing 0x01 replaces the high bit for short blocks
+            let mut block = [0u8; 16];
+            block[..self.buf_len].copy_from_slice(&self.buf[..self.buf_len]);
+            block[self.buf_len] = 0x01;
+            compute_block(&mut self.h, &self.r, &block, 0);
+            wipe(&mut block);
+        }
 
-    let r = BigUint::from_bytes_le(&key[..16]);
-    let r = r & BigUint::from_bytes_be(&CLAMPER);
+        Tag(finish(self.h, &self.s))
+    }
 
-    let s = BigUint::from_bytes_le(&key[16..]);
+    /// Finalizes and compares the result with `tag` in constant time.
+    pub fn verify(self, tag: &Tag) -> Result<(), Error> {
+        if self.finalize() == *tag {
+            Ok(())
+        } else {
+            Err(Error::TagMismatch)
+        }
+    }
+}
 
-    let mut a = BigUint::from(0u8);
+#[cfg(feature = "zeroize")]
+impl Drop for Key {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for Key {}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Poly1305 {
+    fn drop(&mut self) {
+        wipe(&mut self.r);
+        wipe(&mut self.h);
+        wipe(&mut self.s);
+        wipe(&mut self.buf);
+    }
+}
 
-    let mut msg = msg;
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for Poly1305 {}
 
-    let buf = &mut [0u8; 17];
-    while !msg.is_empty() {
-        let l = msg.len().min(16);
-        buf[..l].copy_from_slice(&msg[..l]);
-        buf[l] = 0x01;
+const MASK26: u32 = 0x3ffffff;
 
-        let block = BigUint::from_bytes_le(&buf[..l + 1]);
+fn le32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
 
-        a = ((a + block) * &r) % &p;
+/// Loads `r` from the first half of the key as five 26-bit limbs, clamped as
+/// RFC 8439 Section 2.5 requires.
+fn clamp_r(key: &[u8; 32]) -> [u32; 5] {
+    [
+        le32(&key[0..4]) & 0x3ffffff,
+        (le32(&key[3..7]) >> 2) & 0x3ffff03,
+        (le32(&key[6..10]) >> 4) & 0x3ffc0ff,
+        (le32(&key[9..13]) >> 6) & 0x3f03fff,
+        (le32(&key[12..16]) >> 8) & 0x00fffff,
+    ]
+}
 
-        msg = &msg[l..];
+/// Computes `h = (h + block) * r mod 2^130 - 5` on 26-bit limbs.
+///
+/// `hibit` is `1 << 24` for full blocks (bit 128 of the block) and 0 for a
+/// final block that has already been padded with 0x01.
+fn compute_block(h: &mut [u32; 5], r: &[u32; 5], block: &[u8; 16], hibit: u32) {
+    let [r0, r1, r2, r3, r4] = r.map(u64::from);
+    // 2^130 = 5 mod p, so limbs that overflow past 2^130 fold back times 5
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+    let h0 = (h[0] + (le32(&block[0..4]) & MASK26)) as u64;
+    let h1 = (h[1] + ((le32(&block[3..7]) >> 2) & MASK26)) as u64;
+    let h2 = (h[2] + ((le32(&block[6..10]) >> 4) & MASK26)) as u64;
+    let h3 = (h[3] + ((le32(&block[9..13]) >> 6) & MASK26)) as u64;
+    let h4 = (h[4] + ((le32(&block[12..16]) >> 8) | hibit)) as u64;
+
+    let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+    let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+    let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+    let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+    let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+    // Partial carry propagation keeps every limb within 26 bits plus a little
+    d1 += d0 >> 26;
+    h[0] = d0 as u32 & MASK26;
+    d2 += d1 >> 26;
+    h[1] = d1 as u32 & MASK26;
+    d3 += d2 >> 26;
+    h[2] = d2 as u32 & MASK26;
+    d4 += d3 >> 26;
+    h[3] = d3 as u32 & MASK26;
+    let c = (d4 >> 26) as u32;
+    h[4] = d4 as u32 & MASK26;
+    h[0] += c * 5;
+    h[1] += h[0] >> 26;
+    h[0] &= MASK26;
+}
+
+/// Fully reduces `h` mod 2^130 - 5 and returns `(h + s) mod 2^128`.
+fn finish(mut h: [u32; 5], s: &[u8; 16]) -> [u8; 16] {
+    // Full carry
+    h[2] += h[1] >> 26;
+    h[1] &= MASK26;
+    h[3] += h[2] >> 26;
+    h[2] &= MASK26;
+    h[4] += h[3] >> 26;
+    h[3] &= MASK26;
+    h[0] += (h[4] >> 26) * 5;
+    h[4] &= MASK26;
+    h[1] += h[0] >> 26;
+    h[0] &= MASK26;
+
+    // g = h - p, computed as h + 5 - 2^130
+    let mut g = [0u32; 5];
+    g[0] = h[0] + 5;
+    g[1] = h[1] + (g[0] >> 26);
+    g[0] &= MASK26;
+    g[2] = h[2] + (g[1] >> 26);
+    g[1] &= MASK26;
+    g[3] = h[3] + (g[2] >> 26);
+    g[2] &= MASK26;
+    g[4] = (h[4] + (g[3] >> 26)).wrapping_sub(1 << 26);
+    g[3] &= MASK26;
+
+    // Select g when h >= p (g did not borrow) without branching
+    let mask = (g[4] >> 31).wrapping_sub(1);
+    for i in 0..5 {
+        h[i] = (h[i] & !mask) | (g[i] & mask);
+    }
+
+    // Pack into four 32-bit words (mod 2^128)
+    let h0 = h[0] | (h[1] << 26);
+    let h1 = (h[1] >> 6) | (h[2] << 20);
+    let h2 = (h[2] >> 12) | (h[3] << 14);
+    let h3 = (h[3] >> 18) | (h[4] << 8);
+
+    let mut out = [0u8; 16];
+    let mut carry = 0u64;
+    for (i, hw) in [h0, h1, h2, h3].into_iter().enumerate() {
+        let f = hw as u64 + le32(&s[4 * i..]) as u64 + carry;
+        out[4 * i..4 * (i + 1)].copy_from_slice(&(f as u32).to_le_bytes());
+        carry = f >> 32;
     }
+    wipe(&mut h);
+    wipe(&mut g);
+    out
+}
 
-    let result = a + &s;
+#[cfg(feature = "universal-hash")]
+impl universal_hash::crypto_common::KeySizeUser for Poly1305 {
+    type KeySize = universal_hash::consts::U32;
+}
+
+#[cfg(feature = "universal-hash")]
+impl universal_hash::KeyInit for Poly1305 {
+    fn new(key: &universal_hash::Key<Self>) -> Self {
+        Poly1305::new(&Key((*key).into()))
+    }
+}
 
-    let result = BigUint::to_bytes_le(&result);
+#[cfg(feature = "universal-hash")]
+impl universal_hash::crypto_common::BlockSizeUser for Poly1305 {
+    type BlockSize = universal_hash::consts::U16;
+}
 
-    let mut x = [0u8; 16];
+#[cfg(feature = "universal-hash")]
+impl universal_hash::crypto_common::ParBlocksSizeUser for Poly1305 {
+    type ParBlocksSize = universal_hash::consts::U1;
+}
 
-    let l = result.len().min(16);
-    x[..l].copy_from_slice(&result[..l]);
-    x
+/// Blocks go through [`Poly1305::update`], so they follow on correctly from
+/// any partial block buffered by earlier calls.
+#[cfg(feature = "universal-hash")]
+impl universal_hash::UhfBackend for Poly1305 {
+    fn proc_block(&mut self, block: &universal_hash::Block<Self>) {
+        self.update(block);
+    }
 }
 
-pub fn generate_key(key: [u8; 32], nonce: [u8; 12]) -> [u8; 32] {
-    let counter = 0;
-    let s = chacha::State::new(&key, &nonce, counter);
-    let b = s.block();
+#[cfg(feature = "universal-hash")]
+impl universal_hash::UniversalHash for Poly1305 {
+    fn update_with_backend(
+        &mut self,
+        f: impl universal_hash::UhfClosure<BlockSize = Self::BlockSize>,
+    ) {
+        f.call(self);
+    }
+
+    fn finalize(self) -> universal_hash::Block<Self> {
+        Poly1305::finalize(self).0.into()
+    }
+}
 
-    let mut x = [0u8; 32];
-    x.copy_from_slice(&b[..32]);
-    x
+/// Derives the one-time key for a ChaCha20 key and nonce (RFC 8439 Section 2.6).
+pub fn generate_key(key: [u8; 32], nonce: [u8; 12]) -> Key {
+    // Written straight into the key so no copy is left behind
+    let mut otk = Key([0u8; 32]);
+    chacha::ChaCha20::new(key, nonce)
+        .write_keystream(&mut otk.0)
+        .expect("32 bytes fit in block 0");
+    otk
+}
+
+/// Compares two tags without short-circuiting on the first differing byte.
+fn constant_time_eq(a: &[u8; 16], b: &[u8; 16]) -> bool {
+    let mut diff = 0u8;
+    for i in 0..16 {
+        diff |= a[i] ^ b[i];
+    }
+    core::hint::black_box(diff) == 0
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{vec, vec::Vec};
+
+    use num::BigUint;
+
+    const CLAMPER: [u8; 16] = [
+        0x0f, 0xff, 0xff, 0xfc, 0x0f, 0xff, 0xff, 0xfc, 0x0f, 0xff, 0xff, 0xfc, 0x0f, 0xff, 0xff,
+        0xff,
+    ];
+
+    // Straightforward BigUint version used to cross-check the limb arithmetic
+    fn mac_biguint(key: [u8; 32], msg: &[u8]) -> [u8; 16] {
+        let p = BigUint::from(1u8);
+        let p = p << 130;
+        let p: BigUint = p - 5u8;
+
+        let r = BigUint::from_bytes_le(&key[..16]);
+        let r = r & BigUint::from_bytes_be(&CLAMPER);
+
+        let s = BigUint::from_bytes_le(&key[16..]);
+
+        let mut a = BigUint::from(0u8);
+
+        let mut msg = msg;
+
+        let buf = &mut [0u8; 17];
+        while !msg.is_empty() {
+            let l = msg.len().min(16);
+            buf[..l].copy_from_slice(&msg[..l]);
+            buf[l] = 0x01;
+
+            let block = BigUint::from_bytes_le(&buf[..l + 1]);
+
+            a = ((a + block) * &r) % &p;
+
+            msg = &msg[l..];
+        }
+
+        let result = a + &s;
+
+        let result = BigUint::to_bytes_le(&result);
+
+        let mut x = [0u8; 16];
+
+        let l = result.len().min(16);
+        x[..l].copy_from_slice(&result[..l]);
+        x
+    }
+
+    #[test]
+    fn test_mac_matches_biguint() {
+        // Keystream as a deterministic source of keys and messages
+        let c = chacha::ChaCha20::new([0x42; 32], [0x24; 12]);
+        let mut data = [0u8; 4096];
+        c.encrypt(&mut data, 0).unwrap();
+
+        let mut keys = vec![[0u8; 32], [0xff; 32]];
+        for k in data.chunks_exact(32).take(16) {
+            keys.push(k.try_into().unwrap());
+        }
+
+        let mut msgs: Vec<Vec<u8>> = vec![vec![0xff; 256], vec![0u8; 256]];
+        for i in 0..8 {
+            msgs.push(data[i * 300..i * 300 + 256].to_vec());
+        }
+
+        for key in &keys {
+            for msg in &msgs {
+                for len in [0, 1, 15, 16, 17, 31, 32, 33, 63, 64, 65, 100, 255, 256] {
+                    assert_eq!(
+                        mac(&Key::from(*key), &msg[..len]),
+                        Tag::from(mac_biguint(*key, &msg[..len])),
+                        "key={key:02x?} len={len}"
+                    );
+                }
+            }
+        }
+    }
 
     #[test]
     fn test_mac() {
@@ -74,14 +450,14 @@ mod tests {
                 0x20, 0x47, 0x72, 0x6f, 0x75, 0x70,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c,
                     0x01, 0x27, 0xa9,
-                ]
+                ])
             )
         }
         {
@@ -100,14 +476,14 @@ mod tests {
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                     0x00, 0x00, 0x00,
-                ]
+                ])
             )
         }
         {
@@ -148,14 +524,14 @@ mod tests {
                 0x64, 0x64, 0x72, 0x65, 0x73, 0x73, 0x65, 0x64, 0x20, 0x74, 0x6f,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x36, 0xe5, 0xf6, 0xb5, 0xc5, 0xe0, 0x60, 0x70, 0xf0, 0xef, 0xca, 0x96, 0x22,
                     0x7a, 0x86, 0x3e,
-                ]
+                ])
             )
         }
         {
@@ -196,14 +572,14 @@ mod tests {
                 0x64, 0x64, 0x72, 0x65, 0x73, 0x73, 0x65, 0x64, 0x20, 0x74, 0x6f,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0xf3, 0x47, 0x7e, 0x7c, 0xd9, 0x54, 0x17, 0xaf, 0x89, 0xa6, 0xb8, 0x79, 0x4c,
                     0x31, 0x0c, 0xf0,
-                ]
+                ])
             )
         }
         {
@@ -227,14 +603,14 @@ mod tests {
                 0x2e,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x45, 0x41, 0x66, 0x9a, 0x7e, 0xaa, 0xee, 0x61, 0xe7, 0x08, 0xdc, 0x7c, 0xbc,
                     0xc5, 0xeb, 0x62,
-                ]
+                ])
             )
         }
         {
@@ -250,14 +626,14 @@ mod tests {
                 0xFF, 0xFF,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                     0x00, 0x00, 0x00,
-                ]
+                ])
             )
         }
         {
@@ -273,14 +649,14 @@ mod tests {
                 0x00, 0x00,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                     0x00, 0x00, 0x00,
-                ]
+                ])
             )
         }
         {
@@ -298,14 +674,14 @@ mod tests {
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                     0x00, 0x00, 0x00,
-                ]
+                ])
             )
         }
         {
@@ -323,14 +699,14 @@ mod tests {
                 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                     0x00, 0x00, 0x00,
-                ]
+                ])
             )
         }
         {
@@ -346,14 +722,14 @@ mod tests {
                 0xFF, 0xFF,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0xFA, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
                     0xFF, 0xFF, 0xFF,
-                ]
+                ])
             )
         }
         {
@@ -372,14 +748,14 @@ mod tests {
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x55, 0x00, 0x00, 0x00, 0x00,
                     0x00, 0x00, 0x00,
-                ]
+                ])
             )
         }
         {
@@ -397,18 +773,114 @@ mod tests {
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             ];
 
-            let m = mac(key, &msg);
+            let m = mac(&Key::from(key), &msg);
 
             assert_eq!(
                 m,
-                [
+                Tag::from([
                     0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                     0x00, 0x00, 0x00,
-                ]
+                ])
             )
         }
     }
 
+    #[test]
+    fn test_poly1305_incremental() {
+        let c = chacha::ChaCha20::new([0x42; 32], [0x24; 12]);
+        let mut data = [0u8; 512];
+        c.encrypt(&mut data, 0).unwrap();
+
+        let key = Key::new_from_slice(&data[..32]).unwrap();
+        let msg = &data[32..];
+
+        let want = mac(&key, msg);
+
+        for step in [1, 3, 15, 16, 17, 50, 64, 100] {
+            let mut p = Poly1305::new(&key);
+            for piece in msg.chunks(step) {
+                p.update(piece);
+            }
+            assert_eq!(p.finalize(), want, "step={step}");
+        }
+
+        // Uneven pieces crossing block boundaries in different places
+        let mut p = Poly1305::new(&key);
+        let mut rest = msg;
+        for l in [0, 7, 9, 16, 1, 31, 33] {
+            p.update(&rest[..l]);
+            rest = &rest[l..];
+        }
+        p.update(rest);
+        assert_eq!(p.finalize(), want);
+    }
+
+    #[test]
+    fn test_poly1305_update_padded() {
+        let key = Key::from([
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+            0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+            0x41, 0x49, 0xf5, 0x1b,
+        ]);
+        let a = b"Cryptographic Forum";
+        let b = b"Research Group";
+
+        let mut padded = [0u8; 48];
+        padded[..a.len()].copy_from_slice(a);
+        padded[32..32 + b.len()].copy_from_slice(b);
+        let want = mac(&key, &padded[..32 + b.len()]);
+
+        let mut p = Poly1305::new(&key);
+        p.update(&a[..5]);
+        p.update_padded(&a[5..]);
+        p.update_padded(&[]);
+        p.update(b);
+        assert_eq!(p.finalize(), want);
+    }
+
+    #[test]
+    fn test_poly1305_verify() {
+        // rfc8439 test vector
+        let key = Key::from([
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+            0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+            0x41, 0x49, 0xf5, 0x1b,
+        ]);
+        let msg = b"Cryptographic Forum Research Group";
+        let tag = Tag::from([
+            0xa8, 0x06, 0x1d, 0xc1, 0x30, 0x51, 0x36, 0xc6, 0xc2, 0x2b, 0x8b, 0xaf, 0x0c, 0x01,
+            0x27, 0xa9,
+        ]);
+
+        let mut p = Poly1305::new(&key);
+        p.update(msg);
+        assert_eq!(p.verify(&tag), Ok(()));
+
+        for i in 0..16 {
+            let mut bad = *tag.as_bytes();
+            bad[i] ^= 0x80;
+
+            let mut p = Poly1305::new(&key);
+            p.update(msg);
+            assert_eq!(p.verify(&Tag::from(bad)), Err(Error::TagMismatch));
+        }
+
+        let mut p = Poly1305::new(&key);
+        p.update(&msg[1..]);
+        assert_eq!(p.verify(&tag), Err(Error::TagMismatch));
+    }
+
+    #[test]
+    fn test_key_tag_from_slice() {
+        assert!(Key::new_from_slice(&[0u8; 32]).is_ok());
+        assert!(matches!(
+            Key::new_from_slice(&[0u8; 16]),
+            Err(Error::InvalidLength)
+        ));
+        assert_eq!(Tag::new_from_slice(&[7u8; 16]), Ok(Tag::from([7u8; 16])));
+        assert_eq!(Tag::new_from_slice(&[7u8; 17]), Err(Error::InvalidLength));
+    }
+
     #[test]
     fn test_generate_key() {
         {
@@ -430,7 +902,7 @@ mod tests {
                 0xfd, 0xd1, 0xa6, 0x46,
             ];
 
-            assert_eq!(want, key)
+            assert_eq!(&want, key.as_bytes())
         }
         {
             // rfc8439 test vector#1
@@ -451,7 +923,7 @@ mod tests {
                 0x8b, 0x77, 0x0d, 0xc7,
             ];
 
-            assert_eq!(want, key)
+            assert_eq!(&want, key.as_bytes())
         }
         {
             // rfc8439 test vector#2
@@ -472,7 +944,7 @@ mod tests {
                 0xe3, 0xfb, 0xb7, 0x39,
             ];
 
-            assert_eq!(want, key)
+            assert_eq!(&want, key.as_bytes())
         }
         {
             // rfc8439 test vector#3
@@ -493,7 +965,88 @@ mod tests {
                 0xd2, 0x33, 0x10, 0xae,
             ];
 
-            assert_eq!(want, key)
+            assert_eq!(&want, key.as_bytes())
+        }
+    }
+
+    #[cfg(feature = "universal-hash")]
+    #[test]
+    fn test_universal_hash() {
+        use universal_hash::{KeyInit, UniversalHash};
+
+        // Self tests from poly1305-donna, as run by the RustCrypto poly1305 crate
+        {
+            // r = 2 and s = 0 with m = 2^129 - 1 gives a tag of 3
+            let mut key = [0u8; 32];
+            key[0] = 2;
+
+            let mut p = <Poly1305 as KeyInit>::new(&key.into());
+            UniversalHash::update(&mut p, &[[0xff; 16].into()]);
+
+            let mut want = [0u8; 16];
+            want[0] = 3;
+            assert_eq!(UniversalHash::finalize(p), want.into());
+        }
+        {
+            let total_key = [
+                0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0xff, 0xfe, 0xfd, 0xfc, 0xfb, 0xfa, 0xf9,
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0x00, 0x00, 0x00, 0x00,
+            ];
+            let total_mac = [
+                0x64, 0xaf, 0xe2, 0xe8, 0xd6, 0xad, 0x7b, 0xbd, 0xd2, 0x87, 0xf9, 0x7c, 0x44, 0x62,
+                0x3d, 0x39,
+            ];
+
+            let mut total = <Poly1305 as KeyInit>::new(&total_key.into());
+            for i in 0..256 {
+                let tag = mac(&Key::from([i as u8; 32]), &[i as u8; 256][..i]);
+                UniversalHash::update(&mut total, &[(*tag.as_bytes()).into()]);
+            }
+            assert_eq!(total.verify(&total_mac.into()), Ok(()));
+        }
+
+        // update_padded from the trait matches Poly1305::update_padded
+        let msg = [0x5a; 50];
+        let key = [0x42; 32];
+        let mut a = <Poly1305 as KeyInit>::new(&key.into());
+        UniversalHash::update_padded(&mut a, &msg);
+        let mut b = Poly1305::new(&Key::from(key));
+        b.update_padded(&msg);
+        assert_eq!(
+            UniversalHash::finalize(a),
+            (*b.finalize().as_bytes()).into()
+        );
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize_on_drop() {
+        use core::mem::MaybeUninit;
+        use core::ptr;
+
+        let key = generate_key([0x42; 32], [0x24; 12]);
+        let mut p = Poly1305::new(&key);
+        p.update(&[0x5a; 20]);
+
+        let mut p = MaybeUninit::new(p);
+        let mut key = MaybeUninit::new(key);
+        // SAFETY: both values are initialized and dropped exactly once here;
+        // MaybeUninit never drops them again
+        unsafe {
+            ptr::drop_in_place(p.as_mut_ptr());
+            ptr::drop_in_place(key.as_mut_ptr());
+        }
+
+        let (p, key) = (p.as_ptr(), key.as_ptr());
+        // SAFETY: dropping leaves the memory in place, and only plain-data
+        // fields are read, through raw pointers rather than references
+        unsafe {
+            assert_eq!((&raw const (*p).r).read(), [0u32; 5]);
+            assert_eq!((&raw const (*p).h).read(), [0u32; 5]);
+            assert_eq!((&raw const (*p).s).read(), [0u8; 16]);
+            assert_eq!((&raw const (*p).buf).read(), [0u8; 16]);
+            assert_eq!((&raw const (*key).0).read(), [0u8; 32]);
         }
     }
 }
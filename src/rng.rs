@@ -0,0 +1,351 @@
+//! Seedable random number generator built on the ChaCha block function.
+//!
+//! The seed is the ChaCha key and the state uses the original layout of
+//! [`State::new_legacy`]: a 64-bit block counter followed by a 64-bit stream
+//! id. Output is word for word the same as the `rand_chacha` crate.
+
+use crate::{chacha::State, wipe};
+
+/// Deterministic ChaCha RNG with `ROUNDS` rounds.
+///
+/// Each seed gives 2^64 independent streams of 2^68 words. Outputs are read
+/// from the keystream as little-endian 32-bit words.
+#[derive(Clone)]
+pub struct ChaChaRng<const ROUNDS: usize> {
+    seed: [u8; 32],
+    stream: u64,
+    // Block counter of the words in `buffer`
+    block: u64,
+    buffer: [u32; 16],
+    // Next unread word of `buffer`, 16 once all of it has been used
+    index: usize,
+}
+
+/// RNG using the full 20-round ChaCha.
+pub type ChaCha20Rng = ChaChaRng<20>;
+/// RNG using ChaCha reduced to 12 rounds.
+pub type ChaCha12Rng = ChaChaRng<12>;
+/// RNG using ChaCha reduced to 8 rounds.
+pub type ChaCha8Rng = ChaChaRng<8>;
+
+impl<const ROUNDS: usize> ChaChaRng<ROUNDS> {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let mut rng = ChaChaRng {
+            seed,
+            stream: 0,
+            block: 0,
+            buffer: [0u32; 16],
+            index: 0,
+        };
+        rng.refill();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.index == 16 {
+            self.block = self.block.wrapping_add(1);
+            self.index = 0;
+            self.refill();
+        }
+
+        let w = self.buffer[self.index];
+        self.index += 1;
+        w
+    }
+
+    /// Returns two consecutive words, the first as the low half.
+    pub fn next_u64(&mut self) -> u64 {
+        let lo = self.next_u32() as u64;
+        let hi = self.next_u32() as u64;
+        (hi << 32) | lo
+    }
+
+    /// Fills `dest` with random bytes. A trailing partial word still consumes
+    /// a whole word, as in `rand_chacha`.
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let w = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&w[..chunk.len()]);
+        }
+    }
+
+    pub fn get_seed(&self) -> [u8; 32] {
+        self.seed
+    }
+
+    /// Switches to another stream, keeping the current word position.
+    pub fn set_stream(&mut self, stream: u64) {
+        self.stream = stream;
+        self.refill();
+    }
+
+    pub fn get_stream(&self) -> u64 {
+        self.stream
+    }
+
+    /// Returns the offset of the next output word from the start of the
+    /// stream.
+    pub fn get_word_pos(&self) -> u128 {
+        self.block as u128 * 16 + self.index as u128
+    }
+
+    /// Moves to word offset `word_pos`. Only the low 68 bits are used.
+    pub fn set_word_pos(&mut self, word_pos: u128) {
+        self.block = (word_pos / 16) as u64;
+        self.index = (word_pos % 16) as usize;
+        self.refill();
+    }
+
+    fn refill(&mut self) {
+        let nonce = self.stream.to_le_bytes();
+        let mut block = State::<ROUNDS>::new_legacy(&self.seed, &nonce, self.block).block();
+        for (w, b) in self.buffer.iter_mut().zip(block.chunks_exact(4)) {
+            *w = u32::from_le_bytes(b.try_into().unwrap());
+        }
+        wipe(&mut block);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> Drop for ChaChaRng<ROUNDS> {
+    fn drop(&mut self) {
+        wipe(&mut self.seed);
+        wipe(&mut self.buffer);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const ROUNDS: usize> zeroize::ZeroizeOnDrop for ChaChaRng<ROUNDS> {}
+
+#[cfg(feature = "rand_core")]
+impl<const ROUNDS: usize> rand_core::RngCore for ChaChaRng<ROUNDS> {
+    fn next_u32(&mut self) -> u32 {
+        ChaChaRng::next_u32(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        ChaChaRng::next_u64(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        ChaChaRng::fill_bytes(self, dest)
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl<const ROUNDS: usize> rand_core::CryptoRng for ChaChaRng<ROUNDS> {}
+
+#[cfg(feature = "rand_core")]
+impl<const ROUNDS: usize> rand_core::SeedableRng for ChaChaRng<ROUNDS> {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Self {
+        ChaChaRng::from_seed(seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chacha::ChaCha;
+
+    #[test]
+    fn test_true_values() {
+        // Test vectors 1 and 2 from draft-nir-cfrg-chacha20-poly1305-04, as
+        // used by rand_chacha
+        {
+            let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+
+            let mut results = [0u32; 32];
+            for w in results.iter_mut() {
+                *w = rng.next_u32();
+            }
+
+            let want = [
+                0xade0b876, 0x903df1a0, 0xe56a5d40, 0x28bd8653, 0xb819d2bd, 0x1aed8da0, 0xccef36a8,
+                0xc70d778b, 0x7c5941da, 0x8d485751, 0x3fe02477, 0x374ad8b8, 0xf4b8436a, 0x1ca11815,
+                0x69b687c3, 0x8665eeb2, 0xbee7079f, 0x7a385155, 0x7c97ba98, 0x0d082d73, 0xa0290fcb,
+                0x6965e348, 0x3e53c612, 0xed7aee32, 0x7621b729, 0x434ee69c, 0xb03371d5, 0xd539d874,
+                0x281fed31, 0x45fb0a51, 0x1f0ae1ac, 0x6f4d794b,
+            ];
+            assert_eq!(results, want);
+        }
+        // Test vector 3: block 1 under a key ending in 0x01
+        {
+            let mut seed = [0u8; 32];
+            seed[31] = 1;
+            let mut rng = ChaCha20Rng::from_seed(seed);
+            for _ in 0..16 {
+                rng.next_u32();
+            }
+
+            let mut results = [0u32; 16];
+            for w in results.iter_mut() {
+                *w = rng.next_u32();
+            }
+
+            let want = [
+                0x2452eb3a, 0x9249f8ec, 0x8d829d9b, 0xddd4ceb1, 0xe8252083, 0x60818b01, 0xf38422b8,
+                0x5aaa49c9, 0xbb00ca8e, 0xda3ba7b4, 0xc4b592d1, 0xfdf2732f, 0x4436274e, 0x2561b3c8,
+                0xebdd4aa6, 0xa0136c00,
+            ];
+            assert_eq!(results, want);
+        }
+    }
+
+    #[test]
+    fn test_word_pos() {
+        // Test vector 4 from draft-nir-cfrg-chacha20-poly1305-04: block 2
+        let mut seed = [0u8; 32];
+        seed[1] = 0xff;
+        let want = [
+            0xfb4dd572, 0x4bc42ef1, 0xdf922636, 0x327f1394, 0xa78dea8f, 0x5e269039, 0xa1bebbc1,
+            0xcaf09aae, 0xa25ab213, 0x48a6b46c, 0x1b9d9bcb, 0x092c5be6, 0x546ca624, 0x1bec45d5,
+            0x87f47473, 0x96f0992e,
+        ];
+        let mut results = [0u32; 16];
+
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        for _ in 0..32 {
+            rng.next_u32();
+        }
+        for w in results.iter_mut() {
+            *w = rng.next_u32();
+        }
+        assert_eq!(results, want);
+        assert_eq!(rng.get_word_pos(), 48);
+
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_word_pos(32);
+        for w in results.iter_mut() {
+            *w = rng.next_u32();
+        }
+        assert_eq!(results, want);
+        assert_eq!(rng.get_word_pos(), 48);
+
+        // Partial words and u64s consume whole words
+        let mut buf = [0u8; 32];
+        rng.fill_bytes(&mut buf);
+        assert_eq!(rng.get_word_pos(), 56);
+        rng.fill_bytes(&mut buf[..25]);
+        assert_eq!(rng.get_word_pos(), 63);
+        rng.next_u64();
+        assert_eq!(rng.get_word_pos(), 65);
+        rng.fill_bytes(&mut buf[..1]);
+        assert_eq!(rng.get_word_pos(), 66);
+
+        // Mid-block positions and a jump back
+        rng.set_word_pos(37);
+        assert_eq!(rng.next_u32(), want[5]);
+        rng.set_word_pos(32);
+        assert_eq!(rng.next_u64(), (want[1] as u64) << 32 | want[0] as u64);
+    }
+
+    #[test]
+    fn test_stream() {
+        // Test vector 5 from draft-nir-cfrg-chacha20-poly1305-04: the 8-byte
+        // nonce 00 00 00 00 00 00 00 02 as a little-endian stream id
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+        rng.set_stream(2u64 << 56);
+        assert_eq!(rng.get_stream(), 2u64 << 56);
+
+        let mut results = [0u32; 16];
+        for w in results.iter_mut() {
+            *w = rng.next_u32();
+        }
+
+        let want = [
+            0x374dc6c2, 0x3736d58c, 0xb904e24a, 0xcd3f93ef, 0x88228b1a, 0x96a4dfb3, 0x5b76ab72,
+            0xc727ee54, 0x0e0e978a, 0xf3145c95, 0x1b748ea8, 0xf786c297, 0x99c28f5f, 0x628314e8,
+            0x398a19fa, 0x6ded1b53,
+        ];
+        assert_eq!(results, want);
+
+        // Changing the stream keeps the position
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+        rng.set_word_pos(5);
+        rng.set_stream(2u64 << 56);
+        assert_eq!(rng.next_u32(), want[5]);
+    }
+
+    #[test]
+    fn test_true_bytes() {
+        let mut rng = ChaCha20Rng::from_seed([0u8; 32]);
+        let mut results = [0u8; 32];
+        rng.fill_bytes(&mut results);
+
+        let want = [
+            0x76, 0xb8, 0xe0, 0xad, 0xa0, 0xf1, 0x3d, 0x90, 0x40, 0x5d, 0x6a, 0xe5, 0x53, 0x86,
+            0xbd, 0x28, 0xbd, 0xd2, 0x19, 0xb8, 0xa0, 0x8d, 0xed, 0x1a, 0xa8, 0x36, 0xef, 0xcc,
+            0x8b, 0x77, 0x0d, 0xc7,
+        ];
+        assert_eq!(results, want);
+    }
+
+    #[test]
+    fn test_reduced_rounds() {
+        // While the counter stays below 2^32 the stream id is the last 8 bytes
+        // of an IETF nonce that starts with four zero bytes
+        fn check<const ROUNDS: usize>() {
+            let seed = [0x3c; 32];
+            let stream = 0x0123456789abcdef_u64;
+
+            let mut rng = ChaChaRng::<ROUNDS>::from_seed(seed);
+            rng.set_stream(stream);
+            let mut got = [0u8; 300];
+            rng.fill_bytes(&mut got);
+
+            let mut nonce = [0u8; 12];
+            nonce[4..].copy_from_slice(&stream.to_le_bytes());
+            let mut want = [0u8; 300];
+            ChaCha::<ROUNDS>::new(seed, nonce)
+                .write_keystream(&mut want)
+                .unwrap();
+
+            assert_eq!(got, want, "rounds={ROUNDS}");
+        }
+        check::<20>();
+        check::<12>();
+        check::<8>();
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn test_rand_core() {
+        use rand_core::{RngCore, SeedableRng};
+
+        // rand_chacha's construction test: seeding one RNG from another
+        let mut seed = [0u8; 32];
+        seed[8] = 1;
+        seed[16] = 2;
+        seed[24] = 3;
+        let mut rng1 = <ChaCha20Rng as SeedableRng>::from_seed(seed);
+        assert_eq!(RngCore::next_u32(&mut rng1), 137206642);
+
+        let mut rng2 = ChaCha20Rng::from_rng(&mut rng1);
+        assert_eq!(RngCore::next_u32(&mut rng2), 1325750369);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize_on_drop() {
+        use core::mem::MaybeUninit;
+        use core::ptr;
+
+        let mut rng = ChaCha20Rng::from_seed([0x42; 32]);
+        rng.next_u32();
+
+        let mut rng = MaybeUninit::new(rng);
+        // SAFETY: `rng` is initialized and dropped exactly once here;
+        // MaybeUninit never drops it again
+        unsafe { ptr::drop_in_place(rng.as_mut_ptr()) };
+
+        let rng = rng.as_ptr();
+        // SAFETY: dropping leaves the memory in place, and only plain-data
+        // fields are read, through raw pointers rather than references
+        unsafe {
+            assert_eq!((&raw const (*rng).seed).read(), [0u8; 32]);
+            assert_eq!((&raw const (*rng).buffer).read(), [0u32; 16]);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! STREAM online authenticated encryption (Hoang, Reyhanitabar, Rogaway and
+//! Vizár, "Online Authenticated-Encryption and its Nonce-Reuse Misuse-Resistance").
+//!
+//! A message is split into segments that are sealed one at a time with
+//! [`ToyAEAD`]. Segment `i` uses the nonce `prefix || i || flag`: the 7-byte
+//! prefix chosen for the message, `i` as a big-endian `u32` and a flag byte
+//! that is 1 only on the last segment. Dropping, reordering or truncating
+//! segments therefore makes decryption fail. This is the same nonce layout as
+//! the `StreamBE32` construction of the RustCrypto `aead` crate.
+
+use crate::Error;
+use crate::aead::ToyAEAD;
+
+/// Builds the nonce of segment `counter`.
+fn segment_nonce(prefix: &[u8; 7], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..7].copy_from_slice(prefix);
+    nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// Seals a message segment by segment.
+///
+/// The prefix must be unique per message under the same key.
+pub struct StreamEncryptor {
+    aead: ToyAEAD,
+    prefix: [u8; 7],
+    counter: u32,
+}
+
+impl StreamEncryptor {
+    pub fn new(key: [u8; 32], prefix: [u8; 7]) -> Self {
+        Self::from_aead(ToyAEAD::new(key), prefix)
+    }
+
+    pub fn from_aead(aead: ToyAEAD, prefix: [u8; 7]) -> Self {
+        StreamEncryptor {
+            aead,
+            prefix,
+            counter: 0,
+        }
+    }
+
+    /// Seals the next segment in place and returns its tag.
+    ///
+    /// Fails once `u32::MAX` segments have been sealed; the segment at that
+    /// counter can only be sealed with [`encrypt_last`](Self::encrypt_last).
+    pub fn encrypt_next(&mut self, buf: &mut [u8], aad: &[u8]) -> Result<[u8; 16], Error> {
+        if self.counter == u32::MAX {
+            return Err(Error::CounterExhausted);
+        }
+
+        let nonce = segment_nonce(&self.prefix, self.counter, false);
+        let tag = self.aead.seal(nonce, buf, aad)?;
+        self.counter += 1;
+        Ok(tag)
+    }
+
+    /// Seals the final segment in place and returns its tag.
+    pub fn encrypt_last(self, buf: &mut [u8], aad: &[u8]) -> Result<[u8; 16], Error> {
+        let nonce = segment_nonce(&self.prefix, self.counter, true);
+        self.aead.seal(nonce, buf, aad)
+    }
+}
+
+/// Opens the segments produced by a [`StreamEncryptor`], in order.
+pub struct StreamDecryptor {
+    aead: ToyAEAD,
+    prefix: [u8; 7],
+    counter: u32,
+}
+
+impl StreamDecryptor {
+    pub fn new(key: [u8; 32], prefix: [u8; 7]) -> Self {
+        Self::from_aead(ToyAEAD::new(key), prefix)
+    }
+
+    pub fn from_aead(aead: ToyAEAD, prefix: [u8; 7]) -> Self {
+        StreamDecryptor {
+            aead,
+            prefix,
+            counter: 0,
+        }
+    }
+
+    /// Opens the next segment in place. On failure the buffer and the segment
+    /// counter are left unchanged.
+    pub fn decrypt_next(&mut self, buf: &mut [u8], aad: &[u8], tag: [u8; 16]) -> Result<(), Error> {
+        if self.counter == u32::MAX {
+            return Err(Error::CounterExhausted);
+        }
+
+        let nonce = segment_nonce(&self.prefix, self.counter, false);
+        self.aead.open(nonce, buf, aad, tag)?;
+        self.counter += 1;
+        Ok(())
+    }
+
+    /// Opens the final segment in place. Fails if the segment was not sealed
+    /// with [`StreamEncryptor::encrypt_last`], which catches truncated streams.
+    pub fn decrypt_last(self, buf: &mut [u8], aad: &[u8], tag: [u8; 16]) -> Result<(), Error> {
+        let nonce = segment_nonce(&self.prefix, self.counter, true);
+        self.aead.open(nonce, buf, aad, tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const KEY: [u8; 32] = [0x42; 32];
+    const PREFIX: [u8; 7] = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+
+    /// Seals `segments` as one stream, the last one with `encrypt_last`.
+    fn seal_all(segments: &[&[u8]]) -> Vec<(Vec<u8>, [u8; 16])> {
+        let mut enc = StreamEncryptor::new(KEY, PREFIX);
+        let (last, rest) = segments.split_last().unwrap();
+
+        let mut out = Vec::new();
+        for s in rest {
+            let mut buf = s.to_vec();
+            let tag = enc.encrypt_next(&mut buf, b"").unwrap();
+            out.push((buf, tag));
+        }
+        let mut buf = last.to_vec();
+        let tag = enc.encrypt_last(&mut buf, b"").unwrap();
+        out.push((buf, tag));
+        out
+    }
+
+    #[test]
+    fn test_segment_nonce() {
+        assert_eq!(
+            segment_nonce(&PREFIX, 0x0a0b0c0d, false),
+            [
+                0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x0a, 0x0b, 0x0c, 0x0d, 0x00
+            ]
+        );
+        assert_eq!(segment_nonce(&PREFIX, 1, true)[7..], [0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let segments: [&[u8]; 3] = [b"first segment", b"second", b""];
+        let sealed = seal_all(&segments);
+
+        // Each segment is ToyAEAD under its own nonce
+        let mut buf = segments[1].to_vec();
+        let tag = ToyAEAD::new(KEY)
+            .seal(segment_nonce(&PREFIX, 1, false), &mut buf, b"")
+            .unwrap();
+        assert_eq!((buf, tag), sealed[1]);
+
+        let mut dec = StreamDecryptor::new(KEY, PREFIX);
+        for (i, (ct, tag)) in sealed[..2].iter().enumerate() {
+            let mut buf = ct.clone();
+            dec.decrypt_next(&mut buf, b"", *tag).unwrap();
+            assert_eq!(buf, segments[i]);
+        }
+        let (ct, tag) = &sealed[2];
+        let mut buf = ct.clone();
+        dec.decrypt_last(&mut buf, b"", *tag).unwrap();
+        assert_eq!(buf, segments[2]);
+    }
+
+    #[test]
+    fn test_stream_truncation_and_reordering() {
+        let segments: [&[u8]; 3] = [b"one", b"two", b"three"];
+        let sealed = seal_all(&segments);
+
+        // Truncated: the second segment presented as the last one
+        {
+            let mut dec = StreamDecryptor::new(KEY, PREFIX);
+            let mut buf = sealed[0].0.clone();
+            dec.decrypt_next(&mut buf, b"", sealed[0].1).unwrap();
+
+            let mut buf = sealed[1].0.clone();
+            let result = dec.decrypt_last(&mut buf, b"", sealed[1].1);
+            assert_eq!(result, Err(Error::TagMismatch));
+            assert_eq!(buf, sealed[1].0);
+        }
+        // Reordered: the second segment first
+        {
+            let mut dec = StreamDecryptor::new(KEY, PREFIX);
+            let mut buf = sealed[1].0.clone();
+            let result = dec.decrypt_next(&mut buf, b"", sealed[1].1);
+            assert_eq!(result, Err(Error::TagMismatch));
+
+            // The failed segment does not advance the counter
+            let mut buf = sealed[0].0.clone();
+            dec.decrypt_next(&mut buf, b"", sealed[0].1).unwrap();
+            assert_eq!(buf, segments[0]);
+        }
+        // Extended: the last segment cannot be followed by more data
+        {
+            let mut dec = StreamDecryptor::new(KEY, PREFIX);
+            for (ct, tag) in &sealed[..2] {
+                let mut buf = ct.clone();
+                dec.decrypt_next(&mut buf, b"", *tag).unwrap();
+            }
+            let mut buf = sealed[2].0.clone();
+            let result = dec.decrypt_next(&mut buf, b"", sealed[2].1);
+            assert_eq!(result, Err(Error::TagMismatch));
+        }
+    }
+
+    #[test]
+    fn test_stream_counter_exhausted() {
+        let mut enc = StreamEncryptor::new(KEY, PREFIX);
+        enc.counter = u32::MAX;
+        assert_eq!(
+            enc.encrypt_next(&mut [0u8; 4], b""),
+            Err(Error::CounterExhausted)
+        );
+
+        let mut buf = *b"end";
+        let tag = enc.encrypt_last(&mut buf, b"").unwrap();
+
+        let mut dec = StreamDecryptor::new(KEY, PREFIX);
+        dec.counter = u32::MAX;
+        dec.decrypt_last(&mut buf, b"", tag).unwrap();
+        assert_eq!(&buf, b"end");
+    }
+}